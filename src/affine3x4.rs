@@ -0,0 +1,244 @@
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::ops::Mul;
+use ::vector3::{ Vec3, Vector3 };
+use ::matrices::AffineMatrix;
+
+// An affine transform stored as the top three rows of an AffineMatrix; the bottom row is
+// always `0 0 0 1`. Multiplication follows AffineMatrix: `a * b` applies a, then b.
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[repr(C)]
+pub struct Affine3x4
+{
+    i1 : f32, j1 : f32, k1 : f32, w1 : f32,
+    i2 : f32, j2 : f32, k2 : f32, w2 : f32,
+    i3 : f32, j3 : f32, k3 : f32, w3 : f32
+}
+
+// returned when converting an AffineMatrix whose bottom row is not `0 0 0 1`
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct ProjectiveError
+{
+    pub bottom_row : [f32; 4],
+}
+
+impl fmt::Display for ProjectiveError
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        let r = self.bottom_row;
+        write!(f, "matrix is projective, its bottom row is [{} {} {} {}] rather than [0 0 0 1]", r[0], r[1], r[2], r[3])
+    }
+}
+
+impl Error for ProjectiveError {}
+
+impl Affine3x4
+{
+    pub fn from_row_major(array : Vec<f32>) -> Affine3x4
+    {
+        Affine3x4 {
+            i1: array[0], j1: array[1], k1: array[2], w1: array[3],
+            i2: array[4], j2: array[5], k2: array[6], w2: array[7],
+            i3: array[8], j3: array[9], k3: array[10],w3: array[11],
+        }
+    }
+
+    pub fn identity() -> Affine3x4
+    {
+        Affine3x4 {
+            i1: 1., j1: 0., k1: 0., w1: 0.,
+            i2: 0., j2: 1., k2: 0., w2: 0.,
+            i3: 0., j3: 0., k3: 1., w3: 0.,
+        }
+    }
+
+    pub fn translation(x : f32, y : f32, z : f32) -> Affine3x4
+    {
+        Affine3x4 {
+            i1: 1., j1: 0., k1: 0., w1: x,
+            i2: 0., j2: 1., k2: 0., w2: y,
+            i3: 0., j3: 0., k3: 1., w3: z,
+        }
+    }
+
+    // the twelve stored cells, row by row
+    pub fn as_row_major_vec(&self) -> Vec<f32>
+    {
+        vec![
+            self.i1, self.j1, self.k1, self.w1,
+            self.i2, self.j2, self.k2, self.w2,
+            self.i3, self.j3, self.k3, self.w3,
+        ]
+    }
+
+    pub fn to_matrix(&self) -> AffineMatrix
+    {
+        let mut cells = self.as_row_major_vec();
+        cells.extend_from_slice(&[0., 0., 0., 1.]);
+        AffineMatrix::from_row_major(cells)
+    }
+
+    // m * self, like AffineMatrix::multiply; 36 multiplies rather than 64
+    pub fn multiply(&self, m : Affine3x4) -> Affine3x4
+    {
+        let s = self;
+        Affine3x4 {
+            i1: m.i1 * s.i1 + m.j1 * s.i2 + m.k1 * s.i3,
+            j1: m.i1 * s.j1 + m.j1 * s.j2 + m.k1 * s.j3,
+            k1: m.i1 * s.k1 + m.j1 * s.k2 + m.k1 * s.k3,
+            w1: m.i1 * s.w1 + m.j1 * s.w2 + m.k1 * s.w3 + m.w1,
+            i2: m.i2 * s.i1 + m.j2 * s.i2 + m.k2 * s.i3,
+            j2: m.i2 * s.j1 + m.j2 * s.j2 + m.k2 * s.j3,
+            k2: m.i2 * s.k1 + m.j2 * s.k2 + m.k2 * s.k3,
+            w2: m.i2 * s.w1 + m.j2 * s.w2 + m.k2 * s.w3 + m.w2,
+            i3: m.i3 * s.i1 + m.j3 * s.i2 + m.k3 * s.i3,
+            j3: m.i3 * s.j1 + m.j3 * s.j2 + m.k3 * s.j3,
+            k3: m.i3 * s.k1 + m.j3 * s.k2 + m.k3 * s.k3,
+            w3: m.i3 * s.w1 + m.j3 * s.w2 + m.k3 * s.w3 + m.w3,
+        }
+    }
+
+    pub fn apply_vec3(&self, v : Vector3) -> Vector3
+    {
+        self.apply_direction(v) + Vector3::new(self.w1, self.w2, self.w3)
+    }
+
+    // transforms v as a direction, ignoring translation
+    pub fn apply_direction(&self, v : Vector3) -> Vector3
+    {
+        Vector3::new(
+            self.i1 * v.x() + self.j1 * v.y() + self.k1 * v.z(),
+            self.i2 * v.x() + self.j2 * v.y() + self.k2 * v.z(),
+            self.i3 * v.x() + self.j3 * v.y() + self.k3 * v.z())
+    }
+
+    pub fn determinant(&self) -> f32
+    {
+        let m = self;
+        m.i1 * (m.j2 * m.k3 - m.k2 * m.j3)
+            - m.j1 * (m.i2 * m.k3 - m.k2 * m.i3)
+            + m.k1 * (m.i2 * m.j3 - m.j2 * m.i3)
+    }
+
+    // Inverts the 3x3 part by cofactors and undoes the translation with it. Like
+    // AffineMatrix::inverse, a singular transform gives infinite or NaN cells.
+    pub fn inverse(&self) -> Affine3x4
+    {
+        let m = self;
+        let d = 1. / m.determinant();
+
+        let i1 = (m.j2 * m.k3 - m.k2 * m.j3) * d;
+        let j1 = (m.k1 * m.j3 - m.j1 * m.k3) * d;
+        let k1 = (m.j1 * m.k2 - m.k1 * m.j2) * d;
+        let i2 = (m.k2 * m.i3 - m.i2 * m.k3) * d;
+        let j2 = (m.i1 * m.k3 - m.k1 * m.i3) * d;
+        let k2 = (m.k1 * m.i2 - m.i1 * m.k2) * d;
+        let i3 = (m.i2 * m.j3 - m.j2 * m.i3) * d;
+        let j3 = (m.j1 * m.i3 - m.i1 * m.j3) * d;
+        let k3 = (m.i1 * m.j2 - m.j1 * m.i2) * d;
+
+        Affine3x4 {
+            i1, j1, k1, w1: -(i1 * m.w1 + j1 * m.w2 + k1 * m.w3),
+            i2, j2, k2, w2: -(i2 * m.w1 + j2 * m.w2 + k2 * m.w3),
+            i3, j3, k3, w3: -(i3 * m.w1 + j3 * m.w2 + k3 * m.w3),
+        }
+    }
+}
+
+impl From<Affine3x4> for AffineMatrix
+{
+    fn from(m : Affine3x4) -> AffineMatrix
+    {
+        m.to_matrix()
+    }
+}
+
+impl TryFrom<AffineMatrix> for Affine3x4
+{
+    type Error = ProjectiveError;
+    fn try_from(m : AffineMatrix) -> Result<Affine3x4, ProjectiveError>
+    {
+        let cells = m.as_row_major_vec();
+        if cells[12..] != [0., 0., 0., 1.] {
+            return Err(ProjectiveError { bottom_row: [cells[12], cells[13], cells[14], cells[15]] });
+        }
+        Ok(Affine3x4::from_row_major(cells))
+    }
+}
+
+impl Mul for Affine3x4
+{
+    type Output = Affine3x4;
+    fn mul(self, m : Affine3x4) -> Affine3x4 {
+        self.multiply(m)
+    }
+}
+
+impl Mul<Vector3> for Affine3x4
+{
+    type Output = Vector3;
+    fn mul(self, v : Vector3) -> Vector3 {
+        self.apply_vec3(v)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::affine3x4::{ Affine3x4, ProjectiveError };
+    use ::matrices::AffineMatrix;
+    use ::vector3::Vector3;
+    use std::convert::TryFrom;
+
+    fn compact(m : AffineMatrix) -> Affine3x4 {
+        Affine3x4::try_from(m).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        let m = AffineMatrix::rotation_x(0.4) * AffineMatrix::translation(1., 2., 3.);
+        assert_eq!(AffineMatrix::from(compact(m)), m);
+        assert_eq!(compact(AffineMatrix::identity()), Affine3x4::identity());
+    }
+
+    #[test]
+    fn projective_rejected() {
+        let perspective = AffineMatrix::from_row_major(
+            vec![1., 0., 0., 0.,
+                 0., 1., 0., 0.,
+                 0., 0., 1., 0.,
+                 0., 0., 1., 1.]);
+        assert_eq!(Affine3x4::try_from(perspective), Err(ProjectiveError { bottom_row: [0., 0., 1., 1.] }));
+    }
+
+    #[test]
+    fn multiply_matches_affine_matrix() {
+        let a = AffineMatrix::scale(2., 1., 3.) * AffineMatrix::rotation_z(0.7);
+        let b = AffineMatrix::translation(-1., 4., 2.) * AffineMatrix::rotation_y(1.1);
+        assert_approx_eq!((compact(a) * compact(b)).to_matrix(), a * b);
+    }
+
+    #[test]
+    fn points_and_directions() {
+        let m = AffineMatrix::rotation_z(1.) * AffineMatrix::translation(5., 0., 0.);
+        let v = Vector3::newi(1, 2, 3);
+        assert_approx_eq!(compact(m) * v, m * v);
+        assert_approx_eq!(compact(m).apply_direction(v), m.apply_direction(v));
+    }
+
+    #[test]
+    fn inverse_and_determinant() {
+        let m = AffineMatrix::scale(2., 3., -4.) * AffineMatrix::rotation_x(0.3) * AffineMatrix::translation(1., -2., 3.);
+        let c = compact(m);
+        assert_approx_eq!(c.determinant(), m.determinant(), epsilon = 0.0001);
+        assert_approx_eq!(c.inverse().to_matrix(), m.inverse());
+        assert_approx_eq!((c * c.inverse()).to_matrix(), AffineMatrix::identity());
+        assert_approx_eq!(c.inverse() * (c * Vector3::newi(7, 8, 9)), Vector3::newi(7, 8, 9), epsilon = 0.0001);
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt::Debug;
+use ::vector3::{ Vec3, Vector3 };
+use ::vector4::{ Vec4, Vector4 };
+use ::matrices::{ AffineMatrix, CELL_NAMES };
+use ::affine3x4::Affine3x4;
+use ::matrix3::{ self, Matrix3 };
+use packed_simd::f32x4 as fvec;
+
+// Approximate comparisons, cell by cell. Each comparison first accepts values within an
+// absolute `epsilon`, which is what makes comparisons near zero work; the relative and ULP
+// forms then also accept values that are close for their magnitude.
+
+pub const DEFAULT_EPSILON : f32 = 0.00001;
+pub const DEFAULT_MAX_RELATIVE : f32 = 0.00001;
+pub const DEFAULT_MAX_ULPS : u32 = 4;
+
+pub trait ApproxEq : Debug
+{
+    // the compared values with a name for each, used in failure messages
+    fn cells(&self) -> Vec<(&'static str, f32)>;
+
+    fn abs_diff_eq(&self, other : &Self, epsilon : f32) -> bool
+    {
+        all_cells(self, other, |a, b| abs_diff_eq(a, b, epsilon))
+    }
+
+    fn relative_eq(&self, other : &Self, epsilon : f32, max_relative : f32) -> bool
+    {
+        all_cells(self, other, |a, b| relative_eq(a, b, epsilon, max_relative))
+    }
+
+    fn ulps_eq(&self, other : &Self, epsilon : f32, max_ulps : u32) -> bool
+    {
+        all_cells(self, other, |a, b| ulps_eq(a, b, epsilon, max_ulps))
+    }
+}
+
+fn all_cells<T : ApproxEq + ?Sized, F : Fn(f32, f32) -> bool>(left : &T, right : &T, eq : F) -> bool
+{
+    left.cells().iter().zip(right.cells()).all(|(&(_, a), (_, b))| eq(a, b))
+}
+
+pub fn abs_diff_eq(a : f32, b : f32, epsilon : f32) -> bool
+{
+    // the equality catches matching infinities, whose difference is NaN
+    a == b || (a - b).abs() <= epsilon
+}
+
+pub fn relative_eq(a : f32, b : f32, epsilon : f32, max_relative : f32) -> bool
+{
+    if abs_diff_eq(a, b, epsilon) {
+        return true;
+    }
+    let largest = a.abs().max(b.abs());
+    (a - b).abs() <= largest * max_relative
+}
+
+// Counts the representable floats between a and b. Values of opposite sign are only equal
+// within epsilon, since their bit patterns are not adjacent.
+pub fn ulps_eq(a : f32, b : f32, epsilon : f32, max_ulps : u32) -> bool
+{
+    if abs_diff_eq(a, b, epsilon) {
+        return true;
+    }
+    if a.is_nan() || b.is_nan() || a.is_sign_negative() != b.is_sign_negative() {
+        return false;
+    }
+    let (a, b) = (a.to_bits(), b.to_bits());
+    a.max(b) - a.min(b) <= max_ulps
+}
+
+// Lists the cells that fail the comparison, for the assert macros
+pub fn diff_report<T : ApproxEq + ?Sized, F : Fn(f32, f32) -> bool>(left : &T, right : &T, eq : F) -> String
+{
+    let lines : Vec<String> = left.cells().iter().zip(right.cells())
+        .filter(|&(&(_, a), (_, b))| !eq(a, b))
+        .map(|(&(name, a), (_, b))| format!("  {}: left {}, right {}, difference {}", name, a, b, (a - b).abs()))
+        .collect();
+    lines.join("\n")
+}
+
+impl ApproxEq for f32
+{
+    fn cells(&self) -> Vec<(&'static str, f32)>
+    {
+        vec![("value", *self)]
+    }
+}
+
+impl ApproxEq for Vector3
+{
+    fn cells(&self) -> Vec<(&'static str, f32)>
+    {
+        vec![("x", self.x()), ("y", self.y()), ("z", self.z())]
+    }
+}
+
+impl ApproxEq for Vector4
+{
+    fn cells(&self) -> Vec<(&'static str, f32)>
+    {
+        vec![("x", self.x()), ("y", self.y()), ("z", self.z()), ("w", self.w())]
+    }
+}
+
+impl ApproxEq for fvec
+{
+    fn cells(&self) -> Vec<(&'static str, f32)>
+    {
+        vec![("x", self.extract(0)), ("y", self.extract(1)), ("z", self.extract(2)), ("w", self.extract(3))]
+    }
+}
+
+impl ApproxEq for AffineMatrix
+{
+    fn cells(&self) -> Vec<(&'static str, f32)>
+    {
+        CELL_NAMES.iter().cloned().zip(self.as_row_major_vec()).collect()
+    }
+}
+
+impl ApproxEq for Matrix3
+{
+    fn cells(&self) -> Vec<(&'static str, f32)>
+    {
+        matrix3::CELL_NAMES.iter().cloned().zip(self.as_row_major_vec()).collect()
+    }
+}
+
+impl ApproxEq for Affine3x4
+{
+    fn cells(&self) -> Vec<(&'static str, f32)>
+    {
+        CELL_NAMES.iter().cloned().zip(self.as_row_major_vec()).collect()
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __assert_approx {
+    ( $ left : expr , $ right : expr , $ kind : expr , $ eq : expr ) => ({
+        match ( & ( $ left ) , & ( $ right ) ) {
+            ( left_val , right_val ) => {
+                let eq = $ eq;
+                if ! $ crate::approx::ApproxEq::cells(left_val).iter().zip($ crate::approx::ApproxEq::cells(right_val))
+                    .all(|(&(_, a), (_, b))| eq(a, b)) {
+                    panic!("assertion failed: `{}` (left: `{:?}`, right: `{:?}`)\n{}", $ kind, left_val, right_val,
+                        $ crate::approx::diff_report(left_val, right_val, eq))
+                }
+            }
+        }
+    })
+}
+
+// assert_approx_eq!(a, b) or assert_approx_eq!(a, b, epsilon = 0.001)
+#[macro_export]
+macro_rules! assert_approx_eq {
+    ( $ left : expr , $ right : expr ) => (
+        $crate::assert_approx_eq!($ left, $ right, epsilon = $ crate::approx::DEFAULT_EPSILON)
+    );
+    ( $ left : expr , $ right : expr , epsilon = $ epsilon : expr ) => ({
+        let epsilon : f32 = $ epsilon;
+        $crate::__assert_approx!($ left, $ right, "left ≈ right",
+            |a : f32, b : f32| $ crate::approx::abs_diff_eq(a, b, epsilon))
+    });
+}
+
+// assert_relative_eq!(a, b), optionally followed by `epsilon = ..` and `max_relative = ..`
+#[macro_export]
+macro_rules! assert_relative_eq {
+    ( $ left : expr , $ right : expr ) => (
+        $crate::assert_relative_eq!($ left, $ right, epsilon = $ crate::approx::DEFAULT_EPSILON, max_relative = $ crate::approx::DEFAULT_MAX_RELATIVE)
+    );
+    ( $ left : expr , $ right : expr , max_relative = $ max_relative : expr ) => (
+        $crate::assert_relative_eq!($ left, $ right, epsilon = $ crate::approx::DEFAULT_EPSILON, max_relative = $ max_relative)
+    );
+    ( $ left : expr , $ right : expr , epsilon = $ epsilon : expr , max_relative = $ max_relative : expr ) => ({
+        let (epsilon, max_relative) : (f32, f32) = ($ epsilon, $ max_relative);
+        $crate::__assert_approx!($ left, $ right, "left ≈ right (relative)",
+            |a : f32, b : f32| $ crate::approx::relative_eq(a, b, epsilon, max_relative))
+    });
+}
+
+// assert_ulps_eq!(a, b), optionally followed by `epsilon = ..` and `max_ulps = ..`
+#[macro_export]
+macro_rules! assert_ulps_eq {
+    ( $ left : expr , $ right : expr ) => (
+        $crate::assert_ulps_eq!($ left, $ right, epsilon = $ crate::approx::DEFAULT_EPSILON, max_ulps = $ crate::approx::DEFAULT_MAX_ULPS)
+    );
+    ( $ left : expr , $ right : expr , max_ulps = $ max_ulps : expr ) => (
+        $crate::assert_ulps_eq!($ left, $ right, epsilon = $ crate::approx::DEFAULT_EPSILON, max_ulps = $ max_ulps)
+    );
+    ( $ left : expr , $ right : expr , epsilon = $ epsilon : expr , max_ulps = $ max_ulps : expr ) => ({
+        let (epsilon, max_ulps) : (f32, u32) = ($ epsilon, $ max_ulps);
+        $crate::__assert_approx!($ left, $ right, "left ≈ right (ulps)",
+            |a : f32, b : f32| $ crate::approx::ulps_eq(a, b, epsilon, max_ulps))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use ::approx::{ ApproxEq, abs_diff_eq, relative_eq, ulps_eq, diff_report };
+    use ::vector3::Vector3;
+    use ::vector4::Vector4;
+    use ::matrices::AffineMatrix;
+    use packed_simd::f32x4 as fvec;
+    use std::f32;
+    use std::panic;
+
+    #[test]
+    fn scalar_comparisons() {
+        assert!(abs_diff_eq(1., 1.000001, 0.00001));
+        assert!(!abs_diff_eq(1000., 1000.1, 0.00001));
+        assert!(relative_eq(1000., 1000.001, 0., 0.00001));
+        assert!(!relative_eq(0.001, 0.002, 0., 0.1));
+        assert!(abs_diff_eq(f32::INFINITY, f32::INFINITY, 0.));
+        assert!(!relative_eq(f32::NAN, f32::NAN, 1., 1.));
+    }
+
+    #[test]
+    fn ulps() {
+        let next = f32::from_bits(1f32.to_bits() + 2);
+        assert!(ulps_eq(1., next, 0., 2));
+        assert!(!ulps_eq(1., next, 0., 1));
+        assert!(!ulps_eq(-0.000001, 0.000001, 0., u32::MAX));
+        assert!(ulps_eq(-0.000001, 0.000001, 0.00001, 0));
+    }
+
+    #[test]
+    fn vectors_and_matrices() {
+        assert!(Vector3::new(1., 2., 3.).abs_diff_eq(&Vector3::new(1., 2.000001, 3.), 0.00001));
+        assert!(!Vector4::new(1., 2., 3., 4.).abs_diff_eq(&Vector4::new(1., 2., 3., 4.1), 0.01));
+        assert!(fvec::new(100., 0., 0., 1.).relative_eq(&fvec::new(100.001, 0., 0., 1.), 0., 0.0001));
+
+        let quarter = AffineMatrix::rotation_z(f32::consts::PI / 2.);
+        assert_approx_eq!(quarter * quarter, AffineMatrix::uniform_scale(-1.) * AffineMatrix::scale(1., 1., -1.));
+        assert_relative_eq!(AffineMatrix::uniform_scale(1000.), AffineMatrix::uniform_scale(1000.001) * AffineMatrix::uniform_scale(1.));
+        assert_ulps_eq!(0.1 + 0.2, 0.3f32, max_ulps = 1);
+    }
+
+    #[test]
+    fn report_names_failing_cells() {
+        let left = AffineMatrix::translation(1., 2., 3.);
+        let right = AffineMatrix::translation(1., 2.5, 3.);
+        assert_eq!(diff_report(&left, &right, |a, b| abs_diff_eq(a, b, 0.01)), "  w2: left 2, right 2.5, difference 0.5");
+
+        let message = panic::catch_unwind(|| assert_approx_eq!(Vector3::new(1., 2., 3.), Vector3::new(1., 2., 4.)))
+            .unwrap_err().downcast::<String>().unwrap();
+        assert!(message.ends_with("\n  z: left 3, right 4, difference 1"));
+    }
+}
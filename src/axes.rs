@@ -0,0 +1,164 @@
+use ::vector3::{ Vec3, Vector3 };
+use ::matrices::AffineMatrix;
+
+// Axis conventions, described by which signed axis points to an asset's own right, up and
+// forward (the way it faces). Converting between two conventions keeps those meanings:
+// whatever was up stays up, even if that moves it from +Y to +Z or mirrors the scene.
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Axis
+{
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Handedness
+{
+    Left,
+    Right,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct AxisConvention
+{
+    pub right : Axis,
+    pub up : Axis,
+    pub forward : Axis,
+}
+
+impl Axis
+{
+    pub fn vector(&self) -> Vector3
+    {
+        match *self {
+            Axis::PositiveX => Vector3::i_hat(),
+            Axis::NegativeX => -Vector3::i_hat(),
+            Axis::PositiveY => Vector3::j_hat(),
+            Axis::NegativeY => -Vector3::j_hat(),
+            Axis::PositiveZ => Vector3::k_hat(),
+            Axis::NegativeZ => -Vector3::k_hat(),
+        }
+    }
+}
+
+impl AxisConvention
+{
+    // glTF: +Y up, assets face +Z, right-handed
+    pub const GLTF : AxisConvention = AxisConvention { right: Axis::NegativeX, up: Axis::PositiveY, forward: Axis::PositiveZ };
+    // Blender and most CAD packages: +Z up, assets face -Y (toward the front view), right-handed
+    pub const BLENDER : AxisConvention = AxisConvention { right: Axis::NegativeX, up: Axis::PositiveZ, forward: Axis::NegativeY };
+    // Unity and Direct3D: +Y up, +Z forward, +X right, left-handed
+    pub const UNITY : AxisConvention = AxisConvention { right: Axis::PositiveX, up: Axis::PositiveY, forward: Axis::PositiveZ };
+
+    // panics if two of the axes are parallel
+    pub fn new(right : Axis, up : Axis, forward : Axis) -> AxisConvention
+    {
+        let convention = AxisConvention { right, up, forward };
+        assert!(convention.is_valid(), "axis convention needs three perpendicular axes, got {:?}", convention);
+        convention
+    }
+
+    pub fn is_valid(&self) -> bool
+    {
+        self.right.vector().cross(self.up.vector()).dot(self.forward.vector()) != 0.
+    }
+
+    // In a left-handed convention right × up (by the usual formula) gives forward
+    pub fn handedness(&self) -> Handedness
+    {
+        if self.right.vector().cross(self.up.vector()) == self.forward.vector() { Handedness::Left } else { Handedness::Right }
+    }
+
+    // The matrix taking coordinates in this convention to coordinates in `to`. It is a signed
+    // permutation, a rotation when the handedness matches and a mirror when it does not.
+    pub fn conversion_to(&self, to : &AxisConvention) -> AffineMatrix
+    {
+        assert!(self.is_valid() && to.is_valid(), "axis conventions need three perpendicular axes");
+        let (right, up, forward) = (self.right.vector(), self.up.vector(), self.forward.vector());
+
+        // each unit axis, split into right/up/forward parts and rebuilt in `to`
+        let column = |axis : Vector3| to.right.vector() * right.dot(axis) + to.up.vector() * up.dot(axis) + to.forward.vector() * forward.dot(axis);
+        AffineMatrix::from_basis(column(Vector3::i_hat()), column(Vector3::j_hat()), column(Vector3::k_hat()), Vector3::zero())
+    }
+
+    pub fn convert_point(&self, to : &AxisConvention, p : Vector3) -> Vector3
+    {
+        self.conversion_to(to).apply_vec3(p)
+    }
+
+    // Conjugates m so it does the same thing in `to`: convert back, apply m, convert again.
+    // A rotation about up stays a rotation about up, with its sense reversed by a mirror.
+    pub fn convert_transform(&self, to : &AxisConvention, m : &AffineMatrix) -> AffineMatrix
+    {
+        let conversion = self.conversion_to(to);
+        // signed permutations are orthogonal, so the transpose is the inverse
+        conversion.transpose() * *m * conversion
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::axes::{ Axis, AxisConvention, Handedness };
+    use ::matrices::AffineMatrix;
+    use ::vector3::{ Vec3, Vector3 };
+    use std::f32::consts::PI;
+
+    #[test]
+    fn handedness() {
+        assert_eq!(AxisConvention::GLTF.handedness(), Handedness::Right);
+        assert_eq!(AxisConvention::BLENDER.handedness(), Handedness::Right);
+        assert_eq!(AxisConvention::UNITY.handedness(), Handedness::Left);
+        assert!(!AxisConvention { right: Axis::PositiveX, up: Axis::NegativeX, forward: Axis::PositiveZ }.is_valid());
+    }
+
+    #[test]
+    fn gltf_to_blender_is_a_rotation() {
+        let m = AxisConvention::GLTF.conversion_to(&AxisConvention::BLENDER);
+        assert_approx_eq!(m, AffineMatrix::rotation_x(PI / 2.));
+        assert_eq!(AxisConvention::GLTF.convert_point(&AxisConvention::BLENDER, Vector3::newi(1, 2, 3)), Vector3::newi(1, -3, 2));
+    }
+
+    #[test]
+    fn gltf_to_unity_mirrors_x() {
+        let m = AxisConvention::GLTF.conversion_to(&AxisConvention::UNITY);
+        assert_eq!(m, AffineMatrix::scale(-1., 1., 1.));
+        assert_eq!(m.determinant(), -1.);
+    }
+
+    #[test]
+    fn round_trips() {
+        for &(a, b) in &[(AxisConvention::GLTF, AxisConvention::BLENDER), (AxisConvention::BLENDER, AxisConvention::UNITY)] {
+            assert_eq!(a.conversion_to(&b) * b.conversion_to(&a), AffineMatrix::identity());
+            assert_eq!(a.conversion_to(&a), AffineMatrix::identity());
+        }
+    }
+
+    #[test]
+    fn transforms_keep_their_meaning() {
+        let turn = AffineMatrix::rotation_y(0.5) * AffineMatrix::translation(0., 1., 0.);
+        let p = Vector3::newi(3, -1, 2);
+        for to in &[AxisConvention::BLENDER, AxisConvention::UNITY] {
+            let converted = AxisConvention::GLTF.convert_transform(to, &turn);
+            let expected = AxisConvention::GLTF.convert_point(to, turn.apply_vec3(p));
+            assert_approx_eq!(converted.apply_vec3(AxisConvention::GLTF.convert_point(to, p)), expected);
+        }
+
+        // lifting along glTF's +Y is lifting along Blender's +Z
+        let lift = AxisConvention::GLTF.convert_transform(&AxisConvention::BLENDER, &AffineMatrix::translation(0., 1., 0.));
+        assert_approx_eq!(lift.origin(), Vector3::k_hat());
+    }
+}
@@ -0,0 +1,268 @@
+extern crate affine_transforms;
+
+use std::env;
+use std::fs::File;
+use std::io;
+use std::io::{ BufRead, BufReader, Write };
+use std::process;
+use affine_transforms::vector3::{ Vec3, Vector3 };
+use affine_transforms::matrices::AffineMatrix;
+use affine_transforms::expression::parse_matrix;
+
+const USAGE : &str = "\
+usage: affine [options] <transform>...
+
+Composes the transforms, first argument applied first, e.g.
+    affine 'scale(2)' 'rotate_z(90deg)' 'translate(1, 2, 3)'
+
+options:
+  -c, --column-major    print matrices in column-major order (one column per line)
+  -i, --inverse         also print the inverse
+  -d, --determinant     also print the determinant
+      --decompose       also print translation, rotation and scale; fails for sheared
+                        or flattened transforms
+  -p, --points <file>   transform 'x y z' or 'x,y,z' points from a file ('-' for stdin)
+                        instead of printing the matrix; may be repeated
+  -h, --help            show this message";
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Default)]
+struct Options
+{
+    transforms : Vec<String>,
+    column_major : bool,
+    inverse : bool,
+    determinant : bool,
+    decompose : bool,
+    points : Vec<String>,
+    help : bool,
+}
+
+fn parse_args<I : Iterator<Item = String>>(mut args : I) -> Result<Options, String>
+{
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" | "--column-major" => options.column_major = true,
+            "-i" | "--inverse" => options.inverse = true,
+            "-d" | "--determinant" => options.determinant = true,
+            "--decompose" => options.decompose = true,
+            "-h" | "--help" => options.help = true,
+            "-p" | "--points" => match args.next() {
+                Some(file) => options.points.push(file),
+                None => return Err(format!("{} needs a file name", arg)),
+            },
+            // a leading '-' could also start a negative number, but transforms start with a name
+            _ if arg.starts_with('-') && arg.len() > 1 => return Err(format!("unknown option '{}'", arg)),
+            _ => options.transforms.push(arg),
+        }
+    }
+    if options.transforms.is_empty() && !options.help {
+        return Err("no transforms given".to_string());
+    }
+    Ok(options)
+}
+
+fn compose(transforms : &[String]) -> Result<AffineMatrix, String>
+{
+    let mut result = AffineMatrix::identity();
+    for (i, text) in transforms.iter().enumerate() {
+        let m = parse_matrix(text).map_err(|e| format!("transform {} ('{}'): {}", i + 1, text, e))?;
+        result = result * m;
+    }
+    Ok(result)
+}
+
+fn format_matrix(m : &AffineMatrix, column_major : bool) -> String
+{
+    let values = if column_major { m.as_column_major_vec() } else { m.as_row_major_vec() };
+    values.chunks(4)
+        .map(|line| line.iter().map(|v| v.to_string()).collect::<Vec<String>>().join(" "))
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn format_vector(v : Vector3) -> String
+{
+    format!("{} {} {}", v.x(), v.y(), v.z())
+}
+
+// Transforms one line of point data, keeping its delimiter. Blank and '#' lines pass
+// through; Ok(None) means the line was not numeric, which is allowed for a CSV header.
+fn transform_line(m : &AffineMatrix, line : &str) -> Result<Option<String>, String>
+{
+    let trimmed = line.trim();
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return Ok(Some(line.to_string()));
+    }
+
+    let csv = trimmed.contains(',');
+    let fields : Vec<&str> = if csv { trimmed.split(',').map(|f| f.trim()).collect() } else { trimmed.split_whitespace().collect() };
+    if fields.len() < 3 {
+        return Err(format!("expected 3 coordinates, found {}", fields.len()));
+    }
+    let mut xyz = [0.; 3];
+    for (value, field) in xyz.iter_mut().zip(&fields) {
+        *value = match field.parse::<f32>() {
+            Ok(v) => v,
+            Err(_) => return Ok(None),
+        };
+    }
+
+    // extra columns are carried through untouched
+    let p = m.apply_vec3(Vector3::new(xyz[0], xyz[1], xyz[2]));
+    let mut out = vec![p.x().to_string(), p.y().to_string(), p.z().to_string()];
+    out.extend(fields[3..].iter().map(|f| f.to_string()));
+    Ok(Some(out.join(if csv { "," } else { " " })))
+}
+
+fn transform_points<R : BufRead, W : Write>(m : &AffineMatrix, name : &str, reader : R, out : &mut W) -> Result<(), String>
+{
+    for (number, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| format!("{}: {}", name, e))?;
+        match transform_line(m, &line) {
+            Ok(Some(transformed)) => writeln!(out, "{}", transformed),
+            Ok(None) if number == 0 => writeln!(out, "{}", line),
+            Ok(None) => return Err(format!("{}:{}: invalid number in '{}'", name, number + 1, line)),
+            Err(e) => return Err(format!("{}:{}: {}", name, number + 1, e)),
+        }.map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// decompose only describes shear free matrices with every axis kept, so refuse the rest
+// rather than print a rotation that is not one
+fn decomposition(m : &AffineMatrix, column_major : bool) -> Result<String, String>
+{
+    let (translation, rotation, scale) = m.decompose();
+    if scale.x() == 0. || scale.y() == 0. || scale.z() == 0. || !(scale.x() * scale.y() * scale.z()).is_finite() {
+        return Err("cannot decompose: the transform flattens an axis".to_string());
+    }
+    if !rotation.is_orthonormal(0.0001) {
+        return Err("cannot decompose: the transform shears, e.g. a rotation followed by a non-uniform scale".to_string());
+    }
+    Ok(format!("translation: {}\nrotation:\n{}\nscale: {}",
+        format_vector(translation), format_matrix(&rotation, column_major), format_vector(scale)))
+}
+
+fn run(options : &Options) -> Result<(), String>
+{
+    let m = compose(&options.transforms)?;
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    if !options.points.is_empty() {
+        for name in &options.points {
+            if name == "-" {
+                let stdin = io::stdin();
+                transform_points(&m, "stdin", stdin.lock(), &mut out)?;
+            } else {
+                let file = File::open(name).map_err(|e| format!("{}: {}", name, e))?;
+                transform_points(&m, name, BufReader::new(file), &mut out)?;
+            }
+        }
+        return Ok(());
+    }
+
+    let mut sections = vec![format_matrix(&m, options.column_major)];
+    if options.inverse {
+        sections.push(format!("inverse:\n{}", format_matrix(&m.inverse(), options.column_major)));
+    }
+    if options.determinant {
+        sections.push(format!("determinant: {}", m.determinant()));
+    }
+    if options.decompose {
+        sections.push(decomposition(&m, options.column_major)?);
+    }
+    writeln!(out, "{}", sections.join("\n\n")).map_err(|e| e.to_string())
+}
+
+fn main()
+{
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("affine: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        },
+    };
+    if options.help {
+        println!("{}", USAGE);
+        return;
+    }
+    if let Err(e) = run(&options) {
+        eprintln!("affine: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ parse_args, compose, decomposition, format_matrix, transform_line, transform_points };
+    use affine_transforms::matrices::AffineMatrix;
+
+    fn args(list : &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn options() {
+        let options = parse_args(args(&["-c", "scale(2)", "--points", "-", "--inverse"]).into_iter()).unwrap();
+        assert!(options.column_major && options.inverse);
+        assert_eq!(options.transforms, vec!["scale(2)"]);
+        assert_eq!(options.points, vec!["-"]);
+
+        assert!(parse_args(args(&["--bogus", "scale(2)"]).into_iter()).is_err());
+        assert!(parse_args(args(&["-c"]).into_iter()).is_err());
+    }
+
+    #[test]
+    fn arguments_compose_in_order() {
+        let m = compose(&args(&["translate(1, 0, 0)", "rotate_z(90deg) * scale(2)"])).unwrap();
+        let expected = AffineMatrix::translation(1., 0., 0.) * AffineMatrix::rotation_z(::std::f32::consts::PI / 2.) * AffineMatrix::uniform_scale(2.);
+        assert_eq!(m, expected);
+
+        let error = compose(&args(&["scale(2)", "spin(1)"])).unwrap_err();
+        assert!(error.starts_with("transform 2"));
+    }
+
+    #[test]
+    fn matrix_layouts() {
+        let m = AffineMatrix::translation(1., 2., 3.);
+        assert_eq!(format_matrix(&m, false), "1 0 0 1\n0 1 0 2\n0 0 1 3\n0 0 0 1");
+        assert_eq!(format_matrix(&m, true), "1 0 0 0\n0 1 0 0\n0 0 1 0\n1 2 3 1");
+    }
+
+    #[test]
+    fn decomposition_refuses_shear_and_flattening() {
+        let fine = compose(&args(&["scale(1, 2, 3)", "rotate_z(90deg)", "translate(1, 2, 3)"])).unwrap();
+        assert!(decomposition(&fine, false).unwrap().starts_with("translation: 1 2 3\nrotation:\n"));
+
+        let sheared = compose(&args(&["rotate_z(45deg)", "scale(1, 2, 1)"])).unwrap();
+        assert!(decomposition(&sheared, false).unwrap_err().contains("shears"));
+        let flat = compose(&args(&["scale(1, 0, 1)"])).unwrap();
+        assert!(decomposition(&flat, false).unwrap_err().contains("flattens"));
+    }
+
+    #[test]
+    fn point_lines() {
+        let m = AffineMatrix::translation(1., 2., 3.);
+        assert_eq!(transform_line(&m, "0 0 0").unwrap(), Some("1 2 3".to_string()));
+        assert_eq!(transform_line(&m, "1, 1, 1, red").unwrap(), Some("2,3,4,red".to_string()));
+        assert_eq!(transform_line(&m, "# comment").unwrap(), Some("# comment".to_string()));
+        assert_eq!(transform_line(&m, "x,y,z").unwrap(), None);
+        assert!(transform_line(&m, "1 2").is_err());
+    }
+
+    #[test]
+    fn csv_stream() {
+        let m = AffineMatrix::uniform_scale(2.);
+        let mut out = vec![];
+        transform_points(&m, "points.csv", "x,y,z\n1,2,3\n".as_bytes(), &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "x,y,z\n2,4,6\n");
+
+        let error = transform_points(&m, "points.csv", "1,2,3\nx,y,z\n".as_bytes(), &mut vec![]).unwrap_err();
+        assert!(error.starts_with("points.csv:2"));
+    }
+}
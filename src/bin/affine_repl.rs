@@ -0,0 +1,304 @@
+extern crate affine_transforms;
+
+use std::fs::File;
+use std::io;
+use std::io::{ BufRead, BufReader, Write };
+use affine_transforms::vector3::{ Vec3, Vector3 };
+use affine_transforms::matrices::AffineMatrix;
+use affine_transforms::expression::{ Evaluator, Value };
+
+const PROMPT : &str = "> ";
+
+const HELP : &str = "\
+Enter an expression to print it, or 'name = expression' to bind it. The last result is 'ans'.
+    a = translate(1, 2, 3) * rotate_z(90deg)
+    p = vec(1, 0, 0)
+    inverse(a) * p
+
+commands:
+  :vars               list bound names
+  :history            show the statements entered so far
+  :save <file>        write the statements to a file
+  :load <file>        run the statements in a file
+  :tolerance <value>  print numbers within this distance of an integer as that integer
+  :help               show this message
+  :quit               leave";
+
+struct Session
+{
+    evaluator : Evaluator,
+    history : Vec<String>,
+    tolerance : f32,
+}
+
+enum Outcome
+{
+    Print(String),
+    Quit,
+}
+
+impl Session
+{
+    fn new() -> Session
+    {
+        Session { evaluator: Evaluator::new(), history: vec![], tolerance: 0.000001 }
+    }
+
+    fn execute(&mut self, typed : &str) -> Result<Outcome, String>
+    {
+        let line = typed.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(Outcome::Print(String::new()));
+        }
+        if line.starts_with(':') {
+            return self.command(line);
+        }
+
+        // point at the failing column, below the line as it was typed after the prompt
+        self.statement(typed)
+            .map(Outcome::Print)
+            .map_err(|(column, message)| format!("{}^ {}", " ".repeat(PROMPT.chars().count() + column - 1), message))
+    }
+
+    // Evaluates and records a binding or bare expression. Errors carry a one based character
+    // column within the line as given, leading whitespace included.
+    fn statement(&mut self, typed : &str) -> Result<String, (usize, String)>
+    {
+        let line = typed.trim();
+        let (name, expression) = split_binding(line);
+        // expression is a suffix of line, which starts after typed's leading whitespace
+        let start = (typed.len() - typed.trim_start().len()) + (line.len() - expression.len());
+        let offset = typed[..start].chars().count();
+        let value = self.evaluator.evaluate(expression).map_err(|e| (offset + e.column, e.message))?;
+
+        self.history.push(line.to_string());
+        self.evaluator.set("ans", value);
+        match name {
+            Some(name) => {
+                self.evaluator.set(name, value);
+                Ok(format!("{} = {}", name, self.format(&value)))
+            },
+            None => Ok(self.format(&value)),
+        }
+    }
+
+    fn command(&mut self, line : &str) -> Result<Outcome, String>
+    {
+        let mut parts = line.splitn(2, char::is_whitespace);
+        let command = parts.next().unwrap_or("");
+        let argument = parts.next().map(|a| a.trim()).unwrap_or("");
+
+        match command {
+            ":quit" | ":q" => Ok(Outcome::Quit),
+            ":help" => Ok(Outcome::Print(HELP.to_string())),
+            ":history" => Ok(Outcome::Print(self.history.join("\n"))),
+            ":vars" => {
+                let mut names : Vec<&String> = self.evaluator.variables().keys().collect();
+                names.sort();
+                let lines : Vec<String> = names.iter()
+                    .map(|n| format!("{} = {}", n, self.format(&self.evaluator.variables()[*n])))
+                    .collect();
+                Ok(Outcome::Print(lines.join("\n")))
+            },
+            ":tolerance" => {
+                self.tolerance = argument.parse::<f32>().map_err(|_| format!("invalid tolerance '{}'", argument))?;
+                Ok(Outcome::Print(String::new()))
+            },
+            ":save" => {
+                if argument.is_empty() {
+                    return Err(":save needs a file name".to_string());
+                }
+                let mut file = File::create(argument).map_err(|e| format!("{}: {}", argument, e))?;
+                for statement in &self.history {
+                    writeln!(file, "{}", statement).map_err(|e| format!("{}: {}", argument, e))?;
+                }
+                Ok(Outcome::Print(format!("saved {} statements to {}", self.history.len(), argument)))
+            },
+            ":load" => {
+                if argument.is_empty() {
+                    return Err(":load needs a file name".to_string());
+                }
+                let file = File::open(argument).map_err(|e| format!("{}: {}", argument, e))?;
+                let mut count = 0;
+                for (number, line) in BufReader::new(file).lines().enumerate() {
+                    let typed = line.map_err(|e| format!("{}: {}", argument, e))?;
+                    let line = typed.trim();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    if line.starts_with(':') {
+                        return Err(format!("{}:{}: commands cannot be loaded", argument, number + 1));
+                    }
+                    self.statement(&typed)
+                        .map_err(|(column, message)| format!("{}:{}:{}: {}", argument, number + 1, column, message))?;
+                    count += 1;
+                }
+                Ok(Outcome::Print(format!("loaded {} statements from {}", count, argument)))
+            },
+            _ => Err(format!("unknown command '{}', try :help", command)),
+        }
+    }
+
+    fn format(&self, value : &Value) -> String
+    {
+        match *value {
+            Value::Number(n) => self.number(n),
+            Value::Vector(v) => self.vector(v),
+            Value::Matrix(m) => self.matrix(&m),
+        }
+    }
+
+    fn number(&self, n : f32) -> String
+    {
+        let rounded = if (n - n.round()).abs() <= self.tolerance { n.round() } else { n };
+        // avoid printing "-0"
+        (if rounded == 0. { 0. } else { rounded }).to_string()
+    }
+
+    fn vector(&self, v : Vector3) -> String
+    {
+        format!("<{}, {}, {}>", self.number(v.x()), self.number(v.y()), self.number(v.z()))
+    }
+
+    fn matrix(&self, m : &AffineMatrix) -> String
+    {
+        let cells : Vec<String> = m.as_row_major_vec().iter().map(|&v| self.number(v)).collect();
+        let width = cells.iter().map(|c| c.len()).max().unwrap_or(0);
+        let rows : Vec<String> = cells.chunks(4)
+            .map(|row| row.iter().map(|c| format!("{:>width$}", c, width = width)).collect::<Vec<String>>().join(" "))
+            .map(|row| format!("[ {} ]", row))
+            .collect();
+        format!("\n{}", rows.join("\n"))
+    }
+}
+
+// "name = expression" binds a name; anything else is a bare expression
+fn split_binding(line : &str) -> (Option<&str>, &str)
+{
+    if let Some(index) = line.find('=') {
+        let name = line[..index].trim();
+        let valid = name.chars().next().map(|c| c.is_alphabetic() || c == '_').unwrap_or(false)
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+        if valid {
+            return (Some(name), &line[index + 1..]);
+        }
+    }
+    (None, line)
+}
+
+fn main()
+{
+    let mut session = Session::new();
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+
+    println!("affine transforms repl, :help for commands");
+    loop {
+        print!("{}", PROMPT);
+        stdout.lock().flush().ok();
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {},
+            Err(e) => {
+                eprintln!("{}", e);
+                break;
+            },
+        }
+
+        match session.execute(&line) {
+            Ok(Outcome::Quit) => break,
+            Ok(Outcome::Print(ref text)) if text.is_empty() => {},
+            Ok(Outcome::Print(text)) => println!("{}", text),
+            Err(e) => println!("{}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ Session, Outcome };
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+    use std::time::{ SystemTime, UNIX_EPOCH };
+
+    fn run(session : &mut Session, line : &str) -> String {
+        match session.execute(line) {
+            Ok(Outcome::Print(text)) => text,
+            Ok(Outcome::Quit) => "<quit>".to_string(),
+            Err(e) => format!("error {}", e),
+        }
+    }
+
+    #[test]
+    fn bind_and_use() {
+        let mut session = Session::new();
+        assert_eq!(run(&mut session, "p = vec(1, 0, 0)"), "p = <1, 0, 0>");
+        run(&mut session, "r = rotate_z(90deg)");
+        assert_eq!(run(&mut session, "r * p"), "<0, 1, 0>");
+        assert_eq!(run(&mut session, "inverse(r) * ans"), "<1, 0, 0>");
+    }
+
+    #[test]
+    fn tolerance_formatting() {
+        let mut session = Session::new();
+        assert_eq!(run(&mut session, "scale(2) * rotate_z(90deg)"),
+            "\n[  0 -2  0  0 ]\n[  2  0  0  0 ]\n[  0  0  2  0 ]\n[  0  0  0  1 ]");
+        assert_eq!(run(&mut session, "0.1 + 0.2"), "0.3");
+        run(&mut session, ":tolerance 0.5");
+        assert_eq!(run(&mut session, "0.1 + 0.2"), "0");
+    }
+
+    #[test]
+    fn errors_point_at_column() {
+        let mut session = Session::new();
+        // the caret lines up with the input typed after the prompt
+        assert_eq!(run(&mut session, "m = scale(2) * spin(1)"), "error                  ^ unknown function 'spin'");
+        assert!(session.history.is_empty());
+        assert!(run(&mut session, ":frobnicate").starts_with("error unknown command"));
+    }
+
+    #[test]
+    fn error_columns_count_characters() {
+        let mut session = Session::new();
+        let caret = |column : usize, message : &str| format!("error {}^ {}", " ".repeat(2 + column - 1), message);
+        // indentation is kept, so the caret still sits under the typed text
+        assert_eq!(run(&mut session, "   x = spin(1)\n"), caret(8, "unknown function 'spin'"));
+        // 'é' is two bytes but one column
+        assert_eq!(run(&mut session, "é = 2 * spin(1)"), caret(9, "unknown function 'spin'"));
+        assert_eq!(run(&mut session, "1 + é"), caret(5, "unknown variable 'é'"));
+    }
+
+    // a path no other test, or concurrent run of this one, will use
+    fn temp_path(name : &str) -> PathBuf {
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().subsec_nanos();
+        env::temp_dir().join(format!("affine_repl_{}_{}_{}.txt", name, process::id(), nanos))
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = temp_path("save_and_load");
+        let path = path.to_str().unwrap();
+
+        let mut session = Session::new();
+        run(&mut session, "a = translate(1, 2, 3)");
+        run(&mut session, "b = a * a");
+        assert_eq!(run(&mut session, &format!(":save {}", path)), format!("saved 2 statements to {}", path));
+
+        let mut restored = Session::new();
+        assert_eq!(run(&mut restored, &format!(":load {}", path)), format!("loaded 2 statements from {}", path));
+        assert_eq!(run(&mut restored, "b * vec(0, 0, 0)"), "<2, 4, 6>");
+        let mut expected = session.history.clone();
+        expected.push("b * vec(0, 0, 0)".to_string());
+        assert_eq!(restored.history, expected);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn quit() {
+        assert_eq!(run(&mut Session::new(), ":quit"), "<quit>");
+    }
+}
@@ -0,0 +1,658 @@
+use std::sync::Arc;
+use ::vector3::{ Vec3, Vector3 };
+use ::matrices::AffineMatrix;
+use ::mesh::Mesh;
+use ::kdtree::coordinate;
+
+// Bounding volume hierarchies for picking: one over the triangles of a mesh, built with the
+// surface area heuristic, and one over instances of shared meshes placed by a matrix. Rays
+// are taken into each instance's object space by its inverse rather than moving triangles.
+// Boxes are (min, max) corner pairs, as Mesh::bounding_box returns them.
+
+// The direction need not be unit length. Hits are reported as the parameter t along
+// origin + t * direction, which a transformed ray keeps, so hits from different
+// instances compare directly.
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Ray
+{
+    pub origin : Vector3,
+    pub direction : Vector3,
+}
+
+impl Ray
+{
+    pub fn new(origin : Vector3, direction : Vector3) -> Ray
+    {
+        Ray { origin, direction }
+    }
+
+    pub fn at(&self, t : f32) -> Vector3
+    {
+        self.origin + self.direction * t
+    }
+
+    pub fn transformed(&self, m : &AffineMatrix) -> Ray
+    {
+        Ray { origin: m.apply_vec3(self.origin), direction: m.apply_direction(self.direction) }
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Hit
+{
+    // index of the triangle the BVH was built from
+    pub triangle : usize,
+    pub t : f32,
+    // barycentric weights of the second and third corners
+    pub u : f32,
+    pub v : f32,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct InstanceHit
+{
+    pub instance : usize,
+    pub hit : Hit,
+}
+
+// cost of visiting a node relative to testing one triangle
+const TRAVERSAL_COST : f32 = 0.125;
+const BINS : usize = 12;
+const MAX_LEAF : usize = 8;
+
+// Nodes are stored depth first: an interior node's left child follows it directly and
+// `right` holds the other one. Leaves cover order[start..start + count].
+#[derive(Debug)]
+#[derive(Clone)]
+struct Node
+{
+    min : Vector3,
+    max : Vector3,
+    start : usize,
+    count : usize,
+    right : usize,
+}
+
+// A hierarchy over anything with a bounding box; `order` maps leaf ranges to item indices
+#[derive(Debug)]
+#[derive(Clone)]
+struct Hierarchy
+{
+    nodes : Vec<Node>,
+    order : Vec<usize>,
+}
+
+enum Visit
+{
+    Node(Vector3, Vector3),
+    Item(usize),
+}
+
+impl Hierarchy
+{
+    fn new(bounds : &[(Vector3, Vector3)]) -> Hierarchy
+    {
+        let centroids : Vec<Vector3> = bounds.iter().map(|&(min, max)| (min + max) * 0.5).collect();
+        let mut hierarchy = Hierarchy { nodes: vec![], order: (0..bounds.len()).collect() };
+        if !bounds.is_empty() {
+            hierarchy.split(bounds, &centroids, 0, bounds.len());
+        }
+        hierarchy
+    }
+
+    // Binned SAH: for each axis, bucket the centroids and cost every split between buckets
+    // as TRAVERSAL_COST + (area_left * count_left + area_right * count_right) / area.
+    fn split(&mut self, bounds : &[(Vector3, Vector3)], centroids : &[Vector3], start : usize, end : usize) -> usize
+    {
+        let index = self.nodes.len();
+        let (min, max) = enclose(self.order[start..end].iter().map(|&i| bounds[i]));
+        let count = end - start;
+        self.nodes.push(Node { min, max, start, count, right: 0 });
+        if count <= 2 {
+            return index;
+        }
+
+        let (cmin, cmax) = enclose(self.order[start..end].iter().map(|&i| (centroids[i], centroids[i])));
+        let area = surface_area(min, max);
+        let mut best : Option<(usize, usize, f32)> = None;
+        for axis in 0..3 {
+            let (lo, hi) = (coordinate(cmin, axis), coordinate(cmax, axis));
+            if hi <= lo {
+                continue;
+            }
+            let mut bins : [(usize, Option<(Vector3, Vector3)>); BINS] = [(0, None); BINS];
+            for &i in &self.order[start..end] {
+                let bin = &mut bins[bin_of(centroids[i], axis, lo, hi)];
+                bin.0 += 1;
+                bin.1 = Some(bin.1.map_or(bounds[i], |b| union(b, bounds[i])));
+            }
+
+            // sweep from the right to get the area and count on the right of each split
+            let mut right = [(0, 0.); BINS];
+            let mut acc : (usize, Option<(Vector3, Vector3)>) = (0, None);
+            for s in (1..BINS).rev() {
+                acc = merge_bin(acc, bins[s]);
+                right[s] = (acc.0, acc.1.map_or(0., |(a, b)| surface_area(a, b)));
+            }
+            let mut left : (usize, Option<(Vector3, Vector3)>) = (0, None);
+            for s in 1..BINS {
+                left = merge_bin(left, bins[s - 1]);
+                let (right_count, right_area) = right[s];
+                if left.0 == 0 || right_count == 0 {
+                    continue;
+                }
+                let left_area = left.1.map_or(0., |(a, b)| surface_area(a, b));
+                let cost = TRAVERSAL_COST + (left_area * left.0 as f32 + right_area * right_count as f32) / area;
+                if best.is_none_or(|(_, _, c)| cost < c) {
+                    best = Some((axis, s, cost));
+                }
+            }
+        }
+
+        let (axis, s) = match best {
+            Some((axis, s, cost)) if cost < count as f32 || count > MAX_LEAF => (axis, s),
+            // all centroids coincide, or splitting costs more than testing every triangle
+            _ => return index,
+        };
+        let (lo, hi) = (coordinate(cmin, axis), coordinate(cmax, axis));
+        let (below, above) : (Vec<usize>, Vec<usize>) = self.order[start..end].iter()
+            .partition(|&&i| bin_of(centroids[i], axis, lo, hi) < s);
+        let mid = start + below.len();
+        self.order[start..mid].copy_from_slice(&below);
+        self.order[mid..end].copy_from_slice(&above);
+
+        self.split(bounds, centroids, start, mid);
+        let right = self.split(bounds, centroids, mid, end);
+        self.nodes[index].count = 0;
+        self.nodes[index].right = right;
+        index
+    }
+
+    // Depth first walk; `visit` returns whether to descend into a node. Its answer for an
+    // item is ignored.
+    fn traverse<F : FnMut(Visit) -> bool>(&self, mut visit : F)
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            if !visit(Visit::Node(node.min, node.max)) {
+                continue;
+            }
+            if node.count > 0 {
+                for &i in &self.order[node.start..node.start + node.count] {
+                    visit(Visit::Item(i));
+                }
+            } else {
+                stack.push(node.right);
+                stack.push(n + 1);
+            }
+        }
+    }
+
+    fn bounds(&self) -> Option<(Vector3, Vector3)>
+    {
+        self.nodes.first().map(|n| (n.min, n.max))
+    }
+}
+
+fn bin_of(c : Vector3, axis : usize, lo : f32, hi : f32) -> usize
+{
+    (((coordinate(c, axis) - lo) / (hi - lo) * BINS as f32) as usize).min(BINS - 1)
+}
+
+fn merge_bin(a : (usize, Option<(Vector3, Vector3)>), b : (usize, Option<(Vector3, Vector3)>)) -> (usize, Option<(Vector3, Vector3)>)
+{
+    let bounds = match (a.1, b.1) {
+        (Some(x), Some(y)) => Some(union(x, y)),
+        (x, y) => x.or(y),
+    };
+    (a.0 + b.0, bounds)
+}
+
+fn union(a : (Vector3, Vector3), b : (Vector3, Vector3)) -> (Vector3, Vector3)
+{
+    (Vector3::new(a.0.x().min(b.0.x()), a.0.y().min(b.0.y()), a.0.z().min(b.0.z())),
+     Vector3::new(a.1.x().max(b.1.x()), a.1.y().max(b.1.y()), a.1.z().max(b.1.z())))
+}
+
+// panics on an empty iterator
+fn enclose<I : Iterator<Item = (Vector3, Vector3)>>(mut boxes : I) -> (Vector3, Vector3)
+{
+    let first = boxes.next().expect("nothing to enclose");
+    boxes.fold(first, union)
+}
+
+fn surface_area(min : Vector3, max : Vector3) -> f32
+{
+    let d = max - min;
+    2. * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+}
+
+fn triangle_bounds(t : &[Vector3; 3]) -> (Vector3, Vector3)
+{
+    union(union((t[0], t[0]), (t[1], t[1])), (t[2], t[2]))
+}
+
+fn boxes_overlap(a : (Vector3, Vector3), b : (Vector3, Vector3)) -> bool
+{
+    (0..3).all(|axis| coordinate(a.0, axis) <= coordinate(b.1, axis) && coordinate(b.0, axis) <= coordinate(a.1, axis))
+}
+
+// the bounding box of a box's eight corners after `m`
+fn transform_box(m : &AffineMatrix, min : Vector3, max : Vector3) -> (Vector3, Vector3)
+{
+    enclose((0..8).map(|i| {
+        let corner = m.apply_vec3(Vector3::new(
+            if i & 1 == 0 { min.x() } else { max.x() },
+            if i & 2 == 0 { min.y() } else { max.y() },
+            if i & 4 == 0 { min.z() } else { max.z() }));
+        (corner, corner)
+    }))
+}
+
+// slab test against [0, max_t]; inv_direction may hold infinities for axis aligned rays
+fn ray_hits_box(origin : Vector3, inv_direction : Vector3, min : Vector3, max : Vector3, max_t : f32) -> bool
+{
+    let mut near = 0f32;
+    let mut far = max_t;
+    for axis in 0..3 {
+        let (o, inv) = (coordinate(origin, axis), coordinate(inv_direction, axis));
+        let t1 = (coordinate(min, axis) - o) * inv;
+        let t2 = (coordinate(max, axis) - o) * inv;
+        near = near.max(t1.min(t2));
+        far = far.min(t1.max(t2));
+    }
+    near <= far
+}
+
+// Möller–Trumbore, two sided; returns (t, u, v)
+fn ray_hits_triangle(ray : &Ray, t : &[Vector3; 3], max_t : f32) -> Option<(f32, f32, f32)>
+{
+    let e1 = t[1] - t[0];
+    let e2 = t[2] - t[0];
+    let p = ray.direction.cross(e2);
+    let det = e1.dot(p);
+    if det == 0. {
+        return None;
+    }
+    let inv = 1. / det;
+    let s = ray.origin - t[0];
+    let u = s.dot(p) * inv;
+    if !(0. ..=1.).contains(&u) {
+        return None;
+    }
+    let q = s.cross(e1);
+    let v = ray.direction.dot(q) * inv;
+    if v < 0. || u + v > 1. {
+        return None;
+    }
+    let d = e2.dot(q) * inv;
+    if d < 0. || d > max_t {
+        return None;
+    }
+    Some((d, u, v))
+}
+
+// Separating axis test: the box's three axes, the triangle's normal and the nine cross
+// products of triangle edges with box axes.
+fn triangle_overlaps_box(t : &[Vector3; 3], min : Vector3, max : Vector3) -> bool
+{
+    let center = (min + max) * 0.5;
+    let half = (max - min) * 0.5;
+    let v = [t[0] - center, t[1] - center, t[2] - center];
+    let edges = [v[1] - v[0], v[2] - v[1], v[0] - v[2]];
+    let units = [Vector3::newi(1, 0, 0), Vector3::newi(0, 1, 0), Vector3::newi(0, 0, 1)];
+
+    let mut axes = units.to_vec();
+    axes.push(edges[0].cross(edges[1]));
+    for e in edges.iter() {
+        axes.extend(units.iter().map(|&u| e.cross(u)));
+    }
+    axes.iter().all(|&a| {
+        let p = [v[0].dot(a), v[1].dot(a), v[2].dot(a)];
+        let r = half.x() * a.x().abs() + half.y() * a.y().abs() + half.z() * a.z().abs();
+        p[0].min(p[1]).min(p[2]) <= r && p[0].max(p[1]).max(p[2]) >= -r
+    })
+}
+
+fn reciprocal(v : Vector3) -> Vector3
+{
+    Vector3::new(1. / v.x(), 1. / v.y(), 1. / v.z())
+}
+
+// A hierarchy over one set of triangles, in their own (object) space
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Bvh
+{
+    triangles : Vec<[Vector3; 3]>,
+    hierarchy : Hierarchy,
+}
+
+impl Bvh
+{
+    pub fn new(triangles : Vec<[Vector3; 3]>) -> Bvh
+    {
+        let bounds : Vec<(Vector3, Vector3)> = triangles.iter().map(triangle_bounds).collect();
+        Bvh { hierarchy: Hierarchy::new(&bounds), triangles }
+    }
+
+    // triangle indices match Mesh::triangles
+    pub fn from_mesh(mesh : &Mesh) -> Bvh
+    {
+        Bvh::new(mesh.triangles())
+    }
+
+    pub fn triangles(&self) -> &[[Vector3; 3]]
+    {
+        &self.triangles
+    }
+
+    // None when there are no triangles
+    pub fn bounds(&self) -> Option<(Vector3, Vector3)>
+    {
+        self.hierarchy.bounds()
+    }
+
+    // the closest hit with t in [0, max_t]
+    pub fn intersect(&self, ray : &Ray, max_t : f32) -> Option<Hit>
+    {
+        let inv_direction = reciprocal(ray.direction);
+        let mut best : Option<Hit> = None;
+        self.hierarchy.traverse(|visit| match visit {
+            Visit::Node(min, max) => ray_hits_box(ray.origin, inv_direction, min, max, best.map_or(max_t, |h| h.t)),
+            Visit::Item(i) => {
+                let limit = best.map_or(max_t, |h| h.t);
+                if let Some((t, u, v)) = ray_hits_triangle(ray, &self.triangles[i], limit) {
+                    if best.is_none_or(|h| t < h.t) {
+                        best = Some(Hit { triangle: i, t, u, v });
+                    }
+                }
+                true
+            },
+        });
+        best
+    }
+
+    // indices of the triangles touching the box, in ascending order
+    pub fn within_box(&self, min : Vector3, max : Vector3) -> Vec<usize>
+    {
+        let mut found = vec![];
+        self.hierarchy.traverse(|visit| match visit {
+            Visit::Node(a, b) => boxes_overlap((a, b), (min, max)),
+            Visit::Item(i) => {
+                if triangle_overlaps_box(&self.triangles[i], min, max) {
+                    found.push(i);
+                }
+                true
+            },
+        });
+        found.sort_unstable();
+        found
+    }
+}
+
+// A shared Bvh placed in the world by a matrix
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Instance
+{
+    bvh : Arc<Bvh>,
+    transform : AffineMatrix,
+    inverse : AffineMatrix,
+}
+
+impl Instance
+{
+    // panics if the matrix is not invertible
+    pub fn new(bvh : Arc<Bvh>, transform : AffineMatrix) -> Instance
+    {
+        assert!(transform.determinant() != 0., "cannot place an instance with a singular matrix");
+        Instance { inverse: transform.inverse(), bvh, transform }
+    }
+
+    pub fn bvh(&self) -> &Arc<Bvh>
+    {
+        &self.bvh
+    }
+
+    pub fn transform(&self) -> AffineMatrix
+    {
+        self.transform
+    }
+
+    // world space box around the transformed object box; None for an empty Bvh
+    pub fn bounds(&self) -> Option<(Vector3, Vector3)>
+    {
+        self.bvh.bounds().map(|(min, max)| transform_box(&self.transform, min, max))
+    }
+
+    // `ray` is in world space; t is the same along the world and object space rays
+    pub fn intersect(&self, ray : &Ray, max_t : f32) -> Option<Hit>
+    {
+        self.bvh.intersect(&ray.transformed(&self.inverse), max_t)
+    }
+
+    // Triangles touching the world space box. The object space box is searched first and
+    // each candidate, moved into world space, checked exactly.
+    pub fn within_box(&self, min : Vector3, max : Vector3) -> Vec<usize>
+    {
+        let (local_min, local_max) = transform_box(&self.inverse, min, max);
+        self.bvh.within_box(local_min, local_max).into_iter().filter(|&i| {
+            let t = &self.bvh.triangles[i];
+            let world = [self.transform.apply_vec3(t[0]), self.transform.apply_vec3(t[1]), self.transform.apply_vec3(t[2])];
+            triangle_overlaps_box(&world, min, max)
+        }).collect()
+    }
+}
+
+// A hierarchy over instance bounds, so a ray only enters the instances it can reach
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct Scene
+{
+    instances : Vec<Instance>,
+    // the instances with any triangles, which are all the hierarchy holds
+    placed : Vec<usize>,
+    hierarchy : Hierarchy,
+}
+
+impl Scene
+{
+    pub fn new(instances : Vec<Instance>) -> Scene
+    {
+        let (placed, bounds) : (Vec<usize>, Vec<(Vector3, Vector3)>) = instances.iter().enumerate()
+            .filter_map(|(i, instance)| instance.bounds().map(|b| (i, b)))
+            .unzip();
+        Scene { hierarchy: Hierarchy::new(&bounds), instances, placed }
+    }
+
+    pub fn instances(&self) -> &[Instance]
+    {
+        &self.instances
+    }
+
+    pub fn bounds(&self) -> Option<(Vector3, Vector3)>
+    {
+        self.hierarchy.bounds()
+    }
+
+    pub fn intersect(&self, ray : &Ray, max_t : f32) -> Option<InstanceHit>
+    {
+        let inv_direction = reciprocal(ray.direction);
+        let mut best : Option<InstanceHit> = None;
+        self.hierarchy.traverse(|visit| match visit {
+            Visit::Node(min, max) => ray_hits_box(ray.origin, inv_direction, min, max, best.map_or(max_t, |h| h.hit.t)),
+            Visit::Item(i) => {
+                let instance = self.placed[i];
+                if let Some(hit) = self.instances[instance].intersect(ray, best.map_or(max_t, |h| h.hit.t)) {
+                    if best.is_none_or(|b| hit.t < b.hit.t) {
+                        best = Some(InstanceHit { instance, hit });
+                    }
+                }
+                true
+            },
+        });
+        best
+    }
+
+    // (instance, triangle) pairs touching the box, sorted
+    pub fn within_box(&self, min : Vector3, max : Vector3) -> Vec<(usize, usize)>
+    {
+        let mut found = vec![];
+        self.hierarchy.traverse(|visit| match visit {
+            Visit::Node(a, b) => boxes_overlap((a, b), (min, max)),
+            Visit::Item(i) => {
+                let instance = self.placed[i];
+                found.extend(self.instances[instance].within_box(min, max).into_iter().map(|t| (instance, t)));
+                true
+            },
+        });
+        found.sort_unstable();
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use ::bvh::{ Bvh, Instance, Scene, Ray, MAX_LEAF, triangle_overlaps_box };
+    use ::matrices::AffineMatrix;
+    use ::mesh::Mesh;
+    use ::vector3::{ Vec3, Vector3 };
+
+    // a bumpy sheet of 2 * n * n triangles over [0, n] in x and y
+    fn sheet(n : u32) -> Mesh {
+        let mut positions = vec![];
+        for j in 0..n + 1 {
+            for i in 0..n + 1 {
+                let (x, y) = (i as f32, j as f32);
+                positions.push(Vector3::new(x, y, (x * 0.9).sin() * (y * 0.7).cos()));
+            }
+        }
+        let mut indices = vec![];
+        for j in 0..n {
+            for i in 0..n {
+                let a = j * (n + 1) + i;
+                indices.extend_from_slice(&[a, a + 1, a + n + 2, a, a + n + 2, a + n + 1]);
+            }
+        }
+        Mesh::new(positions, indices)
+    }
+
+    fn rays() -> Vec<Ray> {
+        (0..200).map(|i| {
+            let f = i as f32;
+            let origin = Vector3::new(8. + (f * 12.9898).sin() * 10., 8. + (f * 78.233).sin() * 10., 6.);
+            Ray::new(origin, Vector3::new((f * 3.1).sin() * 0.5, (f * 1.7).cos() * 0.5, -1.))
+        }).collect()
+    }
+
+    fn brute_force(triangles : &[[Vector3; 3]], ray : &Ray) -> Option<(usize, f32)> {
+        let single = |t : &[Vector3; 3]| Bvh::new(vec![*t]).intersect(ray, f32::INFINITY).map(|h| h.t);
+        triangles.iter().enumerate()
+            .filter_map(|(i, t)| single(t).map(|d| (i, d)))
+            .fold(None, |best : Option<(usize, f32)>, (i, d)| if best.is_none_or(|b| d < b.1) { Some((i, d)) } else { best })
+    }
+
+    #[test]
+    fn rays_match_brute_force() {
+        let mesh = sheet(16);
+        let bvh = Bvh::from_mesh(&mesh);
+        assert!(bvh.hierarchy.nodes.len() > 1);
+        assert!(bvh.hierarchy.nodes.iter().all(|n| n.count <= MAX_LEAF));
+
+        let mut hits = 0;
+        for ray in rays() {
+            let found = bvh.intersect(&ray, f32::INFINITY).map(|h| (h.triangle, h.t));
+            assert_eq!(found, brute_force(&mesh.triangles(), &ray));
+            hits += found.is_some() as usize;
+        }
+        assert!(hits > 50);
+    }
+
+    #[test]
+    fn hit_details() {
+        let bvh = Bvh::new(vec![[Vector3::newi(0, 0, 0), Vector3::newi(2, 0, 0), Vector3::newi(0, 2, 0)]]);
+        let ray = Ray::new(Vector3::new(0.5, 0.5, 3.), Vector3::newi(0, 0, -2));
+        let hit = bvh.intersect(&ray, f32::INFINITY).unwrap();
+        assert_approx_eq!(hit.t, 1.5);
+        assert_approx_eq!(hit.u, 0.25);
+        assert_approx_eq!(hit.v, 0.25);
+        assert_approx_eq!(ray.at(hit.t), Vector3::new(0.5, 0.5, 0.));
+
+        assert!(bvh.intersect(&ray, 1.).is_none());
+        assert!(bvh.intersect(&Ray::new(ray.origin, Vector3::newi(0, 0, 1)), f32::INFINITY).is_none());
+        assert!(Bvh::new(vec![]).intersect(&ray, f32::INFINITY).is_none());
+        assert_eq!(Bvh::new(vec![]).bounds(), None);
+    }
+
+    #[test]
+    fn box_queries() {
+        let mesh = sheet(12);
+        let bvh = Bvh::from_mesh(&mesh);
+        let (min, max) = (Vector3::new(2.5, 3.5, -2.), Vector3::new(5.5, 4.5, 2.));
+        let brute : Vec<usize> = (0..mesh.triangle_count()).filter(|&i| triangle_overlaps_box(&mesh.triangles()[i], min, max)).collect();
+        // both triangles of cells 2 to 5 in rows 3 and 4; the box corners touch the diagonals
+        assert_eq!(brute.len(), 2 * 4 * 2);
+        assert_eq!(bvh.within_box(min, max), brute);
+        assert!(bvh.within_box(Vector3::newi(20, 20, 0), Vector3::newi(21, 21, 1)).is_empty());
+    }
+
+    #[test]
+    fn instances_match_transformed_meshes() {
+        let mesh = sheet(8);
+        let shared = Arc::new(Bvh::from_mesh(&mesh));
+        let placements = [
+            AffineMatrix::identity(),
+            AffineMatrix::rotation_z(0.6) * AffineMatrix::translation(10., 0., -1.),
+            AffineMatrix::uniform_scale(0.5) * AffineMatrix::rotation_axis(Vector3::newi(1, 1, 0), 0.3) * AffineMatrix::translation(0., 9., 1.),
+            AffineMatrix::scale(-1., 1., 2.) * AffineMatrix::translation(14., 12., 0.),
+        ];
+        let scene = Scene::new(placements.iter().map(|&m| Instance::new(shared.clone(), m)).collect());
+        let baked : Vec<Bvh> = placements.iter().map(|m| Bvh::from_mesh(&mesh.apply(m))).collect();
+
+        let mut hits = 0;
+        for ray in rays() {
+            let expected = baked.iter().enumerate()
+                .filter_map(|(i, b)| b.intersect(&ray, f32::INFINITY).map(|h| (i, h.t)))
+                .fold(None, |best : Option<(usize, f32)>, (i, t)| if best.is_none_or(|b| t < b.1) { Some((i, t)) } else { best });
+            let found = scene.intersect(&ray, f32::INFINITY);
+            assert_eq!(found.map(|h| h.instance), expected.map(|e| e.0));
+            if let (Some(found), Some(expected)) = (found, expected) {
+                assert_approx_eq!(found.hit.t, expected.1, epsilon = 0.001);
+                hits += 1;
+            }
+        }
+        assert!(hits > 50);
+
+        let (min, max) = (Vector3::newi(3, 3, -3), Vector3::newi(11, 8, 3));
+        let expected : Vec<(usize, usize)> = baked.iter().enumerate()
+            .flat_map(|(i, b)| b.within_box(min, max).into_iter().map(move |t| (i, t)))
+            .collect();
+        assert!(expected.iter().any(|&(i, _)| i == 1));
+        assert_eq!(scene.within_box(min, max), expected);
+    }
+
+    #[test]
+    fn empty_instances() {
+        let empty = Arc::new(Bvh::new(vec![]));
+        let full = Arc::new(Bvh::from_mesh(&sheet(2)));
+        let scene = Scene::new(vec![Instance::new(empty, AffineMatrix::identity()), Instance::new(full, AffineMatrix::translation(0., 0., -5.))]);
+        let hit = scene.intersect(&Ray::new(Vector3::new(0.5, 0.5, 10.), Vector3::newi(0, 0, -1)), f32::INFINITY).unwrap();
+        assert_eq!(hit.instance, 1);
+        assert_eq!(Scene::new(vec![]).intersect(&Ray::new(Vector3::zero(), Vector3::newi(1, 0, 0)), 1.), None);
+    }
+}
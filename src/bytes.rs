@@ -0,0 +1,174 @@
+use std::mem;
+use std::slice;
+use ::vector3::{ Vec3, Vector3 };
+use ::vector4::Vector4;
+use ::matrices::AffineMatrix;
+use ::affine3x4::Affine3x4;
+use ::matrix3::Matrix3;
+
+// Views of vectors and matrices as the raw floats or bytes a graphics API expects, without
+// copying. `Vector3` is three packed floats, `Vector4` four and `AffineMatrix` sixteen in
+// row-major order (declare the uniform `row_major` in GLSL, or upload a `ColumnMajorCopy`).
+
+/// Types made only of f32 fields, so any value can be read as `FLOATS` floats.
+///
+/// # Safety
+///
+/// Implementors must be `repr(C)` with exactly `FLOATS` f32 fields and nothing else.
+pub unsafe trait Pod : Copy
+{
+    const FLOATS : usize;
+
+    fn as_f32_slice(&self) -> &[f32]
+    {
+        as_f32_slice(slice::from_ref(self))
+    }
+
+    fn as_bytes(&self) -> &[u8]
+    {
+        as_bytes(slice::from_ref(self))
+    }
+}
+
+unsafe impl Pod for f32 { const FLOATS : usize = 1; }
+unsafe impl Pod for Vector3 { const FLOATS : usize = 3; }
+unsafe impl Pod for Vector4 { const FLOATS : usize = 4; }
+unsafe impl Pod for AffineMatrix { const FLOATS : usize = 16; }
+unsafe impl Pod for Affine3x4 { const FLOATS : usize = 12; }
+unsafe impl Pod for Matrix3 { const FLOATS : usize = 9; }
+unsafe impl Pod for ColumnMajorCopy { const FLOATS : usize = 16; }
+unsafe impl Pod for PaddedVector3 { const FLOATS : usize = 4; }
+
+pub fn as_f32_slice<T : Pod>(values : &[T]) -> &[f32]
+{
+    // every Pod type is repr(C) over f32 fields, so the floats are contiguous and aligned
+    unsafe { slice::from_raw_parts(values.as_ptr() as *const f32, values.len() * T::FLOATS) }
+}
+
+pub fn as_bytes<T : Pod>(values : &[T]) -> &[u8]
+{
+    unsafe { slice::from_raw_parts(values.as_ptr() as *const u8, mem::size_of_val(values)) }
+}
+
+// A transposed copy of an AffineMatrix, stored column by column as OpenGL, Vulkan and WebGPU
+// assume for a plain `mat4`. Unlike the views above it owns its floats, so build these once
+// per frame from the matrices.
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[repr(C)]
+pub struct ColumnMajorCopy
+{
+    pub values : [f32; 16],
+}
+
+impl From<AffineMatrix> for ColumnMajorCopy
+{
+    fn from(m : AffineMatrix) -> ColumnMajorCopy
+    {
+        let mut values = [0.; 16];
+        values.copy_from_slice(&m.as_column_major_vec());
+        ColumnMajorCopy { values }
+    }
+}
+
+impl From<ColumnMajorCopy> for AffineMatrix
+{
+    fn from(m : ColumnMajorCopy) -> AffineMatrix
+    {
+        AffineMatrix::from_column_major(m.values.to_vec())
+    }
+}
+
+// A Vector3 padded to 16 bytes. Both std140 and std430 give `vec3` a 16 byte alignment, so
+// arrays of vec3 in uniform and storage buffers need this stride rather than 12 bytes.
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[repr(C, align(16))]
+pub struct PaddedVector3
+{
+    pub x : f32,
+    pub y : f32,
+    pub z : f32,
+    pad : f32,
+}
+
+pub type Std140Vector3 = PaddedVector3;
+pub type Std430Vector3 = PaddedVector3;
+
+impl From<Vector3> for PaddedVector3
+{
+    fn from(v : Vector3) -> PaddedVector3
+    {
+        PaddedVector3 { x: v.x(), y: v.y(), z: v.z(), pad: 0. }
+    }
+}
+
+impl From<PaddedVector3> for Vector3
+{
+    fn from(v : PaddedVector3) -> Vector3
+    {
+        Vector3::new(v.x, v.y, v.z)
+    }
+}
+
+pub fn column_major(matrices : &[AffineMatrix]) -> Vec<ColumnMajorCopy>
+{
+    matrices.iter().map(|&m| ColumnMajorCopy::from(m)).collect()
+}
+
+pub fn padded(vectors : &[Vector3]) -> Vec<PaddedVector3>
+{
+    vectors.iter().map(|&v| PaddedVector3::from(v)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use ::bytes::{ Pod, ColumnMajorCopy, PaddedVector3, as_f32_slice, as_bytes, column_major, padded };
+    use ::vector3::Vector3;
+    use ::vector4::Vector4;
+    use ::matrices::AffineMatrix;
+    use std::mem;
+
+    #[test]
+    fn layouts() {
+        assert_eq!(mem::size_of::<Vector3>(), 12);
+        assert_eq!(mem::size_of::<Vector4>(), 16);
+        assert_eq!(mem::size_of::<AffineMatrix>(), 64);
+        assert_eq!(mem::size_of::<PaddedVector3>(), 16);
+        assert_eq!(mem::align_of::<PaddedVector3>(), 16);
+        assert_eq!(mem::size_of::<[PaddedVector3; 3]>(), 48);
+    }
+
+    #[test]
+    fn single_values() {
+        let m = AffineMatrix::translation(1., 2., 3.);
+        assert_eq!(m.as_f32_slice(), &m.as_row_major_vec()[..]);
+        assert_eq!(Vector3::newi(1, 2, 3).as_f32_slice(), &[1., 2., 3.]);
+        assert_eq!(Vector4::newi(1, 2, 3, 4).as_bytes().len(), 16);
+        assert_eq!(2f32.as_bytes(), &2f32.to_bits().to_ne_bytes()[..]);
+    }
+
+    #[test]
+    fn slices() {
+        let points = vec![Vector3::newi(1, 2, 3), Vector3::newi(4, 5, 6)];
+        assert_eq!(as_f32_slice(&points), &[1., 2., 3., 4., 5., 6.]);
+        assert_eq!(as_bytes(&points).len(), 24);
+
+        let padded = padded(&points);
+        assert_eq!(as_f32_slice(&padded), &[1., 2., 3., 0., 4., 5., 6., 0.]);
+        assert_eq!(Vector3::from(padded[1]), points[1]);
+    }
+
+    #[test]
+    fn column_major_copies() {
+        let m = AffineMatrix::translation(1., 2., 3.);
+        let columns = column_major(&[m, m]);
+        assert_eq!(&as_f32_slice(&columns)[12..16], &[1., 2., 3., 1.]);
+        assert_eq!(&as_f32_slice(&columns)[..16], &m.as_column_major_vec()[..]);
+        assert_eq!(AffineMatrix::from(ColumnMajorCopy::from(m)), m);
+    }
+}
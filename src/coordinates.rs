@@ -0,0 +1,253 @@
+use ::vector3::{ Vec3, Vector3 };
+use ::matrices::AffineMatrix;
+
+// Spherical and cylindrical coordinates, and WGS84 geodetic positions with the Earth
+// centered (ECEF) and local east-north-up / north-east-down frames built from them.
+// Angles are radians except latitude and longitude, which are degrees as GPS reports them.
+
+// r, then θ measured from +Z and φ around +Z from +X (the physics convention)
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Spherical
+{
+    pub r : f32,
+    pub theta : f32,
+    pub phi : f32,
+}
+
+// ρ from the Z axis, φ around +Z from +X, and height z
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Cylindrical
+{
+    pub rho : f32,
+    pub phi : f32,
+    pub z : f32,
+}
+
+// Degrees and meters above the ellipsoid, kept in f64: an f32 latitude is only good
+// to about a meter.
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Geodetic
+{
+    pub latitude : f64,
+    pub longitude : f64,
+    pub height : f64,
+}
+
+pub const WGS84_A : f64 = 6378137.0;
+pub const WGS84_F : f64 = 1. / 298.257223563;
+const WGS84_E2 : f64 = WGS84_F * (2. - WGS84_F);
+
+impl Spherical
+{
+    pub fn new(r : f32, theta : f32, phi : f32) -> Spherical
+    {
+        Spherical { r, theta, phi }
+    }
+
+    // the origin comes back as all zeros
+    pub fn from_cartesian(v : Vector3) -> Spherical
+    {
+        let r = v.magnitude();
+        let theta = if r == 0. { 0. } else { (v.z() / r).clamp(-1., 1.).acos() };
+        Spherical { r, theta, phi: v.y().atan2(v.x()) }
+    }
+
+    pub fn to_cartesian(&self) -> Vector3
+    {
+        let (sin_theta, cos_theta) = self.theta.sin_cos();
+        let (sin_phi, cos_phi) = self.phi.sin_cos();
+        Vector3::new(self.r * sin_theta * cos_phi, self.r * sin_theta * sin_phi, self.r * cos_theta)
+    }
+}
+
+impl Cylindrical
+{
+    pub fn new(rho : f32, phi : f32, z : f32) -> Cylindrical
+    {
+        Cylindrical { rho, phi, z }
+    }
+
+    pub fn from_cartesian(v : Vector3) -> Cylindrical
+    {
+        Cylindrical { rho: v.x().hypot(v.y()), phi: v.y().atan2(v.x()), z: v.z() }
+    }
+
+    pub fn to_cartesian(&self) -> Vector3
+    {
+        let (sin_phi, cos_phi) = self.phi.sin_cos();
+        Vector3::new(self.rho * cos_phi, self.rho * sin_phi, self.z)
+    }
+}
+
+impl Geodetic
+{
+    pub fn new(latitude : f64, longitude : f64, height : f64) -> Geodetic
+    {
+        Geodetic { latitude, longitude, height }
+    }
+
+    // Earth centered, Earth fixed meters. Rounding to f32 leaves about half a meter of
+    // precision; use `to_enu` for positions near a local origin.
+    pub fn to_ecef(&self) -> Vector3
+    {
+        let (x, y, z) = self.ecef();
+        Vector3::new(x as f32, y as f32, z as f32)
+    }
+
+    // Inverts `to_ecef` by fixed-point iteration on the latitude, which converges to well
+    // under a millimeter in a few steps away from the Earth's center.
+    pub fn from_ecef(v : Vector3) -> Geodetic
+    {
+        let (x, y, z) = (v.x() as f64, v.y() as f64, v.z() as f64);
+        let p = x.hypot(y);
+        let longitude = y.atan2(x);
+
+        let mut latitude = z.atan2(p * (1. - WGS84_E2));
+        let mut height = 0.;
+        for _ in 0..6 {
+            let n = prime_vertical_radius(latitude);
+            height = if latitude.cos().abs() > 1e-9 { p / latitude.cos() - n } else { z.abs() - n * (1. - WGS84_E2) };
+            latitude = z.atan2(p * (1. - WGS84_E2 * n / (n + height)));
+        }
+        Geodetic { latitude: latitude.to_degrees(), longitude: longitude.to_degrees(), height }
+    }
+
+    // east, north and up meters from `origin`, computed in f64 before rounding
+    pub fn to_enu(&self, origin : &Geodetic) -> Vector3
+    {
+        let (e, n, u) = enu_rows(origin);
+        let (x, y, z) = self.ecef();
+        let (ox, oy, oz) = origin.ecef();
+        let d = [x - ox, y - oy, z - oz];
+        let dot = |row : [f64; 3]| (row[0] * d[0] + row[1] * d[1] + row[2] * d[2]) as f32;
+        Vector3::new(dot(e), dot(n), dot(u))
+    }
+
+    pub fn to_ned(&self, origin : &Geodetic) -> Vector3
+    {
+        let enu = self.to_enu(origin);
+        Vector3::new(enu.y(), enu.x(), -enu.z())
+    }
+
+    fn ecef(&self) -> (f64, f64, f64)
+    {
+        let (sin_lat, cos_lat) = self.latitude.to_radians().sin_cos();
+        let (sin_lon, cos_lon) = self.longitude.to_radians().sin_cos();
+        let n = prime_vertical_radius(self.latitude.to_radians());
+        ((n + self.height) * cos_lat * cos_lon,
+         (n + self.height) * cos_lat * sin_lon,
+         (n * (1. - WGS84_E2) + self.height) * sin_lat)
+    }
+}
+
+fn prime_vertical_radius(latitude : f64) -> f64
+{
+    WGS84_A / (1. - WGS84_E2 * latitude.sin() * latitude.sin()).sqrt()
+}
+
+// the east, north and up unit vectors at origin, in ECEF
+fn enu_rows(origin : &Geodetic) -> ([f64; 3], [f64; 3], [f64; 3])
+{
+    let (sin_lat, cos_lat) = origin.latitude.to_radians().sin_cos();
+    let (sin_lon, cos_lon) = origin.longitude.to_radians().sin_cos();
+    ([-sin_lon, cos_lon, 0.],
+     [-sin_lat * cos_lon, -sin_lat * sin_lon, cos_lat],
+     [cos_lat * cos_lon, cos_lat * sin_lon, sin_lat])
+}
+
+// rows are the local axes in ECEF; the translation moves origin to zero
+fn local_frame(rows : [[f64; 3]; 3], origin : &Geodetic) -> AffineMatrix
+{
+    let (ox, oy, oz) = origin.ecef();
+    let mut cells = Vec::with_capacity(16);
+    for row in rows.iter() {
+        cells.extend(row.iter().map(|&v| v as f32));
+        cells.push(-(row[0] * ox + row[1] * oy + row[2] * oz) as f32);
+    }
+    cells.extend_from_slice(&[0., 0., 0., 1.]);
+    AffineMatrix::from_row_major(cells)
+}
+
+// Takes ECEF points to east-north-up meters about origin. The matrix is built in f64, but
+// applying it to f32 ECEF points still rounds them to about half a meter first.
+pub fn enu_frame(origin : &Geodetic) -> AffineMatrix
+{
+    let (e, n, u) = enu_rows(origin);
+    local_frame([e, n, u], origin)
+}
+
+// Takes ECEF points to north-east-down meters about origin, with the same caveat as enu_frame
+pub fn ned_frame(origin : &Geodetic) -> AffineMatrix
+{
+    let (e, n, u) = enu_rows(origin);
+    local_frame([n, e, [-u[0], -u[1], -u[2]]], origin)
+}
+
+#[cfg(test)]
+mod tests {
+    use ::coordinates::{ Spherical, Cylindrical, Geodetic, WGS84_A, WGS84_F, enu_frame, ned_frame };
+    use ::vector3::{ Vec3, Vector3 };
+    use std::f32::consts::PI;
+
+    #[test]
+    fn spherical_round_trip() {
+        let v = Vector3::newi(1, -2, 3);
+        let s = Spherical::from_cartesian(v);
+        assert_approx_eq!(s.r, 14f32.sqrt());
+        assert_approx_eq!(s.to_cartesian(), v);
+        assert_approx_eq!(Spherical::new(2., PI / 2., PI / 2.).to_cartesian(), Vector3::newi(0, 2, 0));
+        assert_eq!(Spherical::from_cartesian(Vector3::zero()), Spherical::new(0., 0., 0.));
+    }
+
+    #[test]
+    fn cylindrical_round_trip() {
+        let v = Vector3::newi(-3, 4, 7);
+        let c = Cylindrical::from_cartesian(v);
+        assert_approx_eq!(c.rho, 5.);
+        assert_approx_eq!(c.to_cartesian(), v);
+    }
+
+    #[test]
+    fn ecef_reference_points() {
+        assert_eq!(Geodetic::new(0., 0., 0.).to_ecef(), Vector3::new(WGS84_A as f32, 0., 0.));
+        let pole = Geodetic::new(90., 0., 0.).to_ecef();
+        assert_approx_eq!(pole.z(), (WGS84_A * (1. - WGS84_F)) as f32, epsilon = 0.5);
+        assert_approx_eq!(Geodetic::new(0., 90., 100.).to_ecef(), Vector3::new(0., WGS84_A as f32 + 100., 0.), epsilon = 0.5);
+    }
+
+    #[test]
+    fn geodetic_round_trip() {
+        let place = Geodetic::new(47.6205, -122.3493, 184.);
+        let back = Geodetic::from_ecef(place.to_ecef());
+        assert!((back.latitude - place.latitude).abs() < 1e-5);
+        assert!((back.longitude - place.longitude).abs() < 1e-5);
+        assert!((back.height - place.height).abs() < 1.);
+    }
+
+    #[test]
+    fn local_frames() {
+        let origin = Geodetic::new(51.4779, -0.0015, 45.);
+        // about 111 m per thousandth of a degree of latitude
+        let north = Geodetic::new(51.4789, -0.0015, 45.).to_enu(&origin);
+        assert_approx_eq!(north.x(), 0., epsilon = 0.01);
+        assert_approx_eq!(north.y(), 111.26, epsilon = 0.1);
+
+        let above = Geodetic::new(51.4779, -0.0015, 145.);
+        assert_approx_eq!(above.to_enu(&origin), Vector3::newi(0, 0, 100), epsilon = 0.001);
+        assert_approx_eq!(above.to_ned(&origin), Vector3::newi(0, 0, -100), epsilon = 0.001);
+
+        // f32 ECEF input is only good to about a meter
+        assert_approx_eq!(enu_frame(&origin).apply_vec3(above.to_ecef()), Vector3::newi(0, 0, 100), epsilon = 1.);
+        assert_approx_eq!(ned_frame(&origin).apply_vec3(above.to_ecef()), Vector3::newi(0, 0, -100), epsilon = 1.);
+        assert_approx_eq!(enu_frame(&origin).determinant(), 1.);
+    }
+}
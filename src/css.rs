@@ -0,0 +1,398 @@
+use std::error::Error;
+use std::fmt;
+use ::vector3::{ Vec3, Vector3 };
+use ::matrices::AffineMatrix;
+
+// Parsing and formatting of CSS `transform` values and SVG `transform="..."` lists.
+//
+// Functions are listed outermost first, so `translate(..) rotate(..)` rotates a point
+// and then translates it. Angles accept deg, rad, grad and turn; a bare number is taken
+// as degrees (SVG). Lengths may be unitless or px.
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct CssParseError
+{
+    pub position : usize,  // byte offset into the source string
+    pub message : String,
+}
+
+impl fmt::Display for CssParseError
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "transform parse error at {}: {}", self.position, self.message)
+    }
+}
+
+impl Error for CssParseError {}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+enum Unit
+{
+    None,
+    Px,
+    Deg,
+    Rad,
+    Grad,
+    Turn,
+}
+
+struct Argument
+{
+    value : f32,
+    unit : Unit,
+    position : usize,
+}
+
+pub fn parse_transform(text : &str) -> Result<AffineMatrix, CssParseError>
+{
+    let mut parser = Parser { text, position: 0 };
+    let mut result = AffineMatrix::identity();
+
+    parser.skip_separators();
+    if text[parser.position..].trim() == "none" {
+        return Ok(result);
+    }
+
+    while parser.position < text.len() {
+        let start = parser.position;
+        let name = parser.identifier();
+        if name.is_empty() {
+            return Err(parser.error("expected a transform function"));
+        }
+        parser.skip_whitespace();
+        parser.expect('(')?;
+        let args = parser.arguments()?;
+        let function = to_matrix(name, &args, start)?;
+
+        // earlier functions are applied last
+        result = function * result;
+        parser.skip_separators();
+    }
+
+    Ok(result)
+}
+
+// CSS `matrix3d(...)` with the sixteen values in column-major order
+pub fn to_matrix3d(m : &AffineMatrix) -> String
+{
+    let values : Vec<String> = m.as_column_major_vec().iter().map(|v| v.to_string()).collect();
+    format!("matrix3d({})", values.join(", "))
+}
+
+fn to_matrix(name : &str, args : &[Argument], position : usize) -> Result<AffineMatrix, CssParseError>
+{
+    let error = |message : String| Err(CssParseError { position, message });
+    let count = |allowed : &[usize]| -> Result<(), CssParseError> {
+        if allowed.contains(&args.len()) {
+            Ok(())
+        } else {
+            Err(CssParseError { position, message: format!("{}() takes {:?} arguments, found {}", name, allowed, args.len()) })
+        }
+    };
+    let length = |i : usize| length(&args[i]);
+    let number = |i : usize| number(&args[i]);
+    let angle = |i : usize| angle(&args[i]);
+
+    match name.to_ascii_lowercase().as_str() {
+        "matrix" => {
+            count(&[6])?;
+            let v = (0..6).map(|i| if i < 4 { number(i) } else { length(i) }).collect::<Result<Vec<f32>, _>>()?;
+            Ok(AffineMatrix::from_row_major(vec![
+                v[0], v[2], 0., v[4],
+                v[1], v[3], 0., v[5],
+                0.,   0.,   1., 0.,
+                0.,   0.,   0., 1.]))
+        },
+        "matrix3d" => {
+            count(&[16])?;
+            let v = (0..16).map(|i| if (12..15).contains(&i) { length(i) } else { number(i) }).collect::<Result<Vec<f32>, _>>()?;
+            Ok(AffineMatrix::from_column_major(v))
+        },
+        "translate" => {
+            count(&[1, 2])?;
+            let y = if args.len() == 2 { length(1)? } else { 0. };
+            Ok(AffineMatrix::translation(length(0)?, y, 0.))
+        },
+        "translatex" => { count(&[1])?; Ok(AffineMatrix::translation(length(0)?, 0., 0.)) },
+        "translatey" => { count(&[1])?; Ok(AffineMatrix::translation(0., length(0)?, 0.)) },
+        "translatez" => { count(&[1])?; Ok(AffineMatrix::translation(0., 0., length(0)?)) },
+        "translate3d" => { count(&[3])?; Ok(AffineMatrix::translation(length(0)?, length(1)?, length(2)?)) },
+        "scale" => {
+            count(&[1, 2])?;
+            let x = number(0)?;
+            let y = if args.len() == 2 { number(1)? } else { x };
+            Ok(AffineMatrix::scale(x, y, 1.))
+        },
+        "scalex" => { count(&[1])?; Ok(AffineMatrix::scale(number(0)?, 1., 1.)) },
+        "scaley" => { count(&[1])?; Ok(AffineMatrix::scale(1., number(0)?, 1.)) },
+        "scalez" => { count(&[1])?; Ok(AffineMatrix::scale(1., 1., number(0)?)) },
+        "scale3d" => { count(&[3])?; Ok(AffineMatrix::scale(number(0)?, number(1)?, number(2)?)) },
+        "rotate" => {
+            // SVG allows rotate(angle cx cy) about a centre point
+            count(&[1, 3])?;
+            let rotation = AffineMatrix::rotation_z(angle(0)?);
+            if args.len() == 1 {
+                return Ok(rotation);
+            }
+            let (cx, cy) = (length(1)?, length(2)?);
+            Ok(AffineMatrix::translation(-cx, -cy, 0.) * rotation * AffineMatrix::translation(cx, cy, 0.))
+        },
+        "rotatex" => { count(&[1])?; Ok(AffineMatrix::rotation_x(angle(0)?)) },
+        "rotatey" => { count(&[1])?; Ok(AffineMatrix::rotation_y(angle(0)?)) },
+        "rotatez" => { count(&[1])?; Ok(AffineMatrix::rotation_z(angle(0)?)) },
+        "rotate3d" => {
+            count(&[4])?;
+            let axis = Vector3::new(number(0)?, number(1)?, number(2)?);
+            if axis.magnitude_squared() == 0. {
+                return Ok(AffineMatrix::identity());
+            }
+            Ok(AffineMatrix::rotation_axis(axis, angle(3)?))
+        },
+        "skew" => {
+            count(&[1, 2])?;
+            let y = if args.len() == 2 { angle(1)? } else { 0. };
+            Ok(skew(angle(0)?, y))
+        },
+        "skewx" => { count(&[1])?; Ok(skew(angle(0)?, 0.)) },
+        "skewy" => { count(&[1])?; Ok(skew(0., angle(0)?)) },
+        "perspective" => {
+            count(&[1])?;
+            let d = length(0)?;
+            let mut values = AffineMatrix::identity().as_row_major_vec();
+            if d != 0. {
+                values[14] = -1. / d;
+            }
+            Ok(AffineMatrix::from_row_major(values))
+        },
+        _ => error(format!("unknown transform function '{}'", name)),
+    }
+}
+
+fn skew(x : f32, y : f32) -> AffineMatrix
+{
+    AffineMatrix::from_row_major(vec![
+        1.,      x.tan(), 0., 0.,
+        y.tan(), 1.,      0., 0.,
+        0.,      0.,      1., 0.,
+        0.,      0.,      0., 1.])
+}
+
+fn number(arg : &Argument) -> Result<f32, CssParseError>
+{
+    match arg.unit {
+        Unit::None => Ok(arg.value),
+        _ => Err(CssParseError { position: arg.position, message: "expected a plain number".to_string() }),
+    }
+}
+
+fn length(arg : &Argument) -> Result<f32, CssParseError>
+{
+    match arg.unit {
+        Unit::None | Unit::Px => Ok(arg.value),
+        _ => Err(CssParseError { position: arg.position, message: "expected a length".to_string() }),
+    }
+}
+
+// radians
+fn angle(arg : &Argument) -> Result<f32, CssParseError>
+{
+    match arg.unit {
+        Unit::None | Unit::Deg => Ok(arg.value.to_radians()),
+        Unit::Rad => Ok(arg.value),
+        Unit::Grad => Ok(arg.value * ::std::f32::consts::PI / 200.),
+        Unit::Turn => Ok(arg.value * 2. * ::std::f32::consts::PI),
+        Unit::Px => Err(CssParseError { position: arg.position, message: "expected an angle".to_string() }),
+    }
+}
+
+struct Parser<'a>
+{
+    text : &'a str,
+    position : usize,
+}
+
+impl<'a> Parser<'a>
+{
+    fn error(&self, message : &str) -> CssParseError
+    {
+        CssParseError { position: self.position, message: message.to_string() }
+    }
+
+    fn peek(&self) -> Option<char>
+    {
+        self.text[self.position..].chars().next()
+    }
+
+    fn take_while<F : Fn(char) -> bool>(&mut self, f : F) -> &'a str
+    {
+        let start = self.position;
+        while let Some(c) = self.peek() {
+            if !f(c) { break; }
+            self.position += c.len_utf8();
+        }
+        &self.text[start..self.position]
+    }
+
+    fn skip_whitespace(&mut self)
+    {
+        self.take_while(char::is_whitespace);
+    }
+
+    // SVG lists may separate functions with commas as well as whitespace
+    fn skip_separators(&mut self)
+    {
+        self.take_while(|c| c.is_whitespace() || c == ',');
+    }
+
+    fn identifier(&mut self) -> &'a str
+    {
+        self.take_while(|c| c.is_ascii_alphanumeric())
+    }
+
+    fn expect(&mut self, expected : char) -> Result<(), CssParseError>
+    {
+        if self.peek() == Some(expected) {
+            self.position += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", expected)))
+        }
+    }
+
+    fn arguments(&mut self) -> Result<Vec<Argument>, CssParseError>
+    {
+        let mut args = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(')') {
+                self.position += 1;
+                return Ok(args);
+            }
+            if !args.is_empty() && self.peek() == Some(',') {
+                self.position += 1;
+                self.skip_whitespace();
+            }
+            args.push(self.argument()?);
+        }
+    }
+
+    fn argument(&mut self) -> Result<Argument, CssParseError>
+    {
+        let position = self.position;
+        let bytes = self.text.as_bytes();
+        let digit = |i : usize| i < bytes.len() && bytes[i].is_ascii_digit();
+
+        let mut end = position;
+        if end < bytes.len() && (bytes[end] == b'-' || bytes[end] == b'+') {
+            end += 1;
+        }
+        while digit(end) || (end < bytes.len() && bytes[end] == b'.') {
+            end += 1;
+        }
+        // an exponent only if digits follow, so "1em" keeps its unit
+        if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+            let sign = end + 1 < bytes.len() && (bytes[end + 1] == b'-' || bytes[end + 1] == b'+');
+            let digits = if sign { end + 2 } else { end + 1 };
+            if digit(digits) {
+                end = digits;
+                while digit(end) { end += 1; }
+            }
+        }
+        let literal = &self.text[position..end];
+        self.position = end;
+        let value = literal.parse::<f32>()
+            .map_err(|_| CssParseError { position, message: format!("invalid number '{}'", literal) })?;
+
+        let unit_position = self.position;
+        let unit = match self.take_while(|c| c.is_ascii_alphabetic() || c == '%').to_ascii_lowercase().as_str() {
+            "" => Unit::None,
+            "px" => Unit::Px,
+            "deg" => Unit::Deg,
+            "rad" => Unit::Rad,
+            "grad" => Unit::Grad,
+            "turn" => Unit::Turn,
+            other => return Err(CssParseError { position: unit_position, message: format!("unsupported unit '{}'", other) }),
+        };
+
+        Ok(Argument { value, unit, position })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::vector3::{ Vec3, Vector3 };
+    use ::matrices::AffineMatrix;
+    use ::css::{ parse_transform, to_matrix3d };
+    use std::f32::consts::PI;
+
+    #[test]
+    fn functions_apply_right_to_left() {
+        // rotate first, then translate
+        let m = parse_transform("translate(10px, 0) rotate(90deg)").unwrap();
+        assert_approx_eq!(m.apply_vec3(Vector3::i_hat()), Vector3::newi(10, 1, 0));
+    }
+
+    #[test]
+    fn angle_units() {
+        let turn = parse_transform("rotateZ(0.25turn)").unwrap();
+        let rad = parse_transform("rotateZ(1.5707964rad)").unwrap();
+        let grad = parse_transform("rotateZ(100grad)").unwrap();
+        for m in [turn, rad, grad].iter() {
+            assert_approx_eq!(m.apply_vec3(Vector3::i_hat()), Vector3::j_hat());
+        }
+    }
+
+    #[test]
+    fn three_dimensional_functions() {
+        let m = parse_transform("translate3d(1px, 2px, 3px) scale3d(2, 2, 2) rotate3d(0, 0, 1, 180deg)").unwrap();
+        assert_approx_eq!(m.apply_vec3(Vector3::i_hat()), Vector3::newi(-1, 2, 3));
+    }
+
+    #[test]
+    fn svg_lists() {
+        // commas between functions and arguments, unitless degrees, rotation about a centre
+        let m = parse_transform("translate(5,5), rotate(180 1 1)").unwrap();
+        assert_approx_eq!(m.apply_vec3(Vector3::zero()), Vector3::newi(7, 7, 0));
+        let skewed = parse_transform("skewX(45)").unwrap();
+        assert_approx_eq!(skewed.apply_vec3(Vector3::j_hat()), Vector3::newi(1, 1, 0));
+    }
+
+    #[test]
+    fn matrix_2d() {
+        let m = parse_transform("matrix(1, 0, 0, 1, 30, -4)").unwrap();
+        assert_eq!(m, AffineMatrix::translation(30., -4., 0.));
+    }
+
+    #[test]
+    fn perspective() {
+        let m = parse_transform("perspective(100px)").unwrap();
+        let p = m.apply_affine(::packed_simd::f32x4::new(0., 0., -100., 1.));
+        assert_eq!(p.extract(3), 2.);
+    }
+
+    #[test]
+    fn matrix3d_round_trip() {
+        let m = AffineMatrix::rotation_x(0.5) * AffineMatrix::translation(1., 2., 3.);
+        let text = to_matrix3d(&m);
+        assert!(text.starts_with("matrix3d(1, 0, 0, 0, "));
+        assert!(text.ends_with(", 1, 2, 3, 1)"));
+        assert_eq!(parse_transform(&text).unwrap(), m);
+        assert_eq!(parse_transform("none").unwrap(), AffineMatrix::identity());
+    }
+
+    #[test]
+    fn errors_point_at_input() {
+        let error = parse_transform("scale(2) spin(3deg)").unwrap_err();
+        assert_eq!(error.position, 9);
+        let error = parse_transform("rotate(3em)").unwrap_err();
+        assert_eq!(error.position, 8);
+        let error = parse_transform("translate(1, 2, 3)").unwrap_err();
+        assert_eq!(error.position, 0);
+        assert!(parse_transform(&format!("rotate({})", PI)).is_ok());
+    }
+}
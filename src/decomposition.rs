@@ -0,0 +1,494 @@
+use ::vector3::{ Vec3, Vector3 };
+use ::matrix3::Matrix3;
+use ::matrices::AffineMatrix;
+
+// Singular value, polar and symmetric eigen decompositions of 3x3 linear maps.
+//
+// The factors are written in the usual math order, A = U Σ Vᵀ and A = R S, where the
+// rightmost factor acts first. With this crate's `*`, which applies the left operand first,
+// that is `v.transpose() * Matrix3::scale(..) * u`; `to_matrix` does it for you.
+
+// how long the Jacobi sweeps may run, and when off-diagonal terms count as zero
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Convergence
+{
+    pub max_sweeps : usize,
+    pub tolerance : f64,
+}
+
+impl Default for Convergence
+{
+    fn default() -> Convergence
+    {
+        Convergence { max_sweeps: 30, tolerance: 1e-12 }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Svd
+{
+    pub u : Matrix3,
+    // non-negative and in decreasing order
+    pub singular_values : Vector3,
+    pub v : Matrix3,
+    pub sweeps : usize,
+    pub converged : bool,
+}
+
+// A = R S: R orthogonal (a reflection when det A < 0) and S symmetric positive semi-definite
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Polar
+{
+    pub rotation : Matrix3,
+    pub stretch : Matrix3,
+}
+
+// eigenvalues in decreasing order, so the principal axis comes first, with unit eigenvectors
+// forming a right-handed orthonormal basis
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct SymmetricEigen
+{
+    pub values : Vector3,
+    pub vectors : [Vector3; 3],
+    pub sweeps : usize,
+    pub converged : bool,
+}
+
+impl Svd
+{
+    pub fn to_matrix(&self) -> Matrix3
+    {
+        let s = self.singular_values;
+        self.v.transpose() * Matrix3::scale(s.x(), s.y(), s.z()) * self.u
+    }
+}
+
+impl Polar
+{
+    pub fn to_matrix(&self) -> Matrix3
+    {
+        self.stretch * self.rotation
+    }
+}
+
+impl SymmetricEigen
+{
+    // V Λ Vᵀ, with the eigenvectors as the columns of V
+    pub fn to_matrix(&self) -> Matrix3
+    {
+        let v = from_columns(self.vectors);
+        let l = self.values;
+        v.transpose() * Matrix3::scale(l.x(), l.y(), l.z()) * v
+    }
+}
+
+type M3 = [[f64; 3]; 3];
+
+fn to_m3(m : &Matrix3) -> M3
+{
+    let c = m.as_row_major_vec();
+    let mut out = [[0.; 3]; 3];
+    for (i, v) in c.iter().enumerate() {
+        out[i / 3][i % 3] = *v as f64;
+    }
+    out
+}
+
+fn from_columns(columns : [Vector3; 3]) -> Matrix3
+{
+    Matrix3::from_column_major(columns.iter().flat_map(|c| vec![c.x(), c.y(), c.z()]).collect())
+}
+
+fn column(m : &M3, j : usize) -> Vector3
+{
+    Vector3::new(m[0][j] as f32, m[1][j] as f32, m[2][j] as f32)
+}
+
+// One-sided (Hestenes) Jacobi: rotate pairs of columns of A until they are mutually
+// orthogonal, accumulating the rotations in V. The column norms are then the singular
+// values and the normalized columns form U.
+pub fn svd(m : &Matrix3, convergence : Convergence) -> Svd
+{
+    let mut w = to_m3(m);
+    let mut v = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+    let mut sweeps = 0;
+    let mut converged = false;
+
+    while sweeps < convergence.max_sweeps && !converged {
+        sweeps += 1;
+        converged = true;
+        for &(p, q) in &[(0, 1), (0, 2), (1, 2)] {
+            let alpha : f64 = (0..3).map(|i| w[i][p] * w[i][p]).sum();
+            let beta : f64 = (0..3).map(|i| w[i][q] * w[i][q]).sum();
+            let gamma : f64 = (0..3).map(|i| w[i][p] * w[i][q]).sum();
+            if gamma.abs() <= convergence.tolerance * (alpha * beta).sqrt() {
+                continue;
+            }
+            converged = false;
+
+            let zeta = (beta - alpha) / (2. * gamma);
+            let t = zeta.signum() / (zeta.abs() + (1. + zeta * zeta).sqrt());
+            let c = 1. / (1. + t * t).sqrt();
+            let s = c * t;
+            for x in [&mut w, &mut v].iter_mut() {
+                for row in x.iter_mut() {
+                    let (a, b) = (row[p], row[q]);
+                    row[p] = c * a - s * b;
+                    row[q] = s * a + c * b;
+                }
+            }
+        }
+    }
+
+    // sort by decreasing singular value, swapping the matching columns of V
+    let norm = |w : &M3, j : usize| (0..3).map(|i| w[i][j] * w[i][j]).sum::<f64>().sqrt();
+    let mut order = [0, 1, 2];
+    order.sort_by(|&a, &b| norm(&w, b).total_cmp(&norm(&w, a)));
+    let sigma : Vec<f64> = order.iter().map(|&j| norm(&w, j)).collect();
+
+    // columns of U for zero singular values are free; complete them to an orthonormal basis
+    let tiny = sigma[0] * 1e-7;
+    let unit = |j : usize| { let c = column(&w, order[j]); c * (1. / sigma[j] as f32) };
+    let u0 = if sigma[0] > 0. { unit(0) } else { Vector3::i_hat() };
+    let u1 = if sigma[1] > tiny { unit(1) } else { u0.orthonormal_basis().0 };
+    let u2 = if sigma[2] > tiny { unit(2) } else { u0.cross(u1) };
+
+    Svd {
+        u: from_columns([u0, u1, u2]),
+        singular_values: Vector3::new(sigma[0] as f32, sigma[1] as f32, sigma[2] as f32),
+        v: from_columns([column(&v, order[0]), column(&v, order[1]), column(&v, order[2])]),
+        sweeps,
+        converged,
+    }
+}
+
+// R = U Vᵀ and S = V Σ Vᵀ from the SVD
+pub fn polar(m : &Matrix3, convergence : Convergence) -> Polar
+{
+    let d = svd(m, convergence);
+    let s = d.singular_values;
+    Polar {
+        rotation: d.v.transpose() * d.u,
+        stretch: d.v.transpose() * Matrix3::scale(s.x(), s.y(), s.z()) * d.v,
+    }
+}
+
+// Cyclic Jacobi: rotates away each off-diagonal term in turn, accumulating the rotations.
+// Slower than the analytic solver but accurate even when eigenvalues nearly coincide.
+// Only the upper triangle of m is read.
+pub fn symmetric_eigen(m : &Matrix3, convergence : Convergence) -> SymmetricEigen
+{
+    let mut a = symmetric_m3(m);
+    let mut v = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+    let scale : f64 = a.iter().flat_map(|row| row.iter()).map(|x| x * x).sum();
+    let mut sweeps = 0;
+    let mut converged = false;
+
+    while sweeps < convergence.max_sweeps {
+        let off = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+        if off <= convergence.tolerance * convergence.tolerance * scale {
+            converged = true;
+            break;
+        }
+        sweeps += 1;
+        for &(p, q) in &[(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0. {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2. * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.).sqrt());
+            let c = 1. / (t * t + 1.).sqrt();
+            let s = t * c;
+
+            // A = Jᵀ A J and V = V J for the rotation J in the (p, q) plane
+            for row in a.iter_mut() {
+                let (akp, akq) = (row[p], row[q]);
+                row[p] = c * akp - s * akq;
+                row[q] = s * akp + c * akq;
+            }
+            let (rp, rq) = (a[p], a[q]);
+            for (k, (&apk, &aqk)) in rp.iter().zip(&rq).enumerate() {
+                a[p][k] = c * apk - s * aqk;
+                a[q][k] = s * apk + c * aqk;
+            }
+            for row in v.iter_mut() {
+                let (vp, vq) = (row[p], row[q]);
+                row[p] = c * vp - s * vq;
+                row[q] = s * vp + c * vq;
+            }
+        }
+    }
+
+    let mut order = [0, 1, 2];
+    order.sort_by(|&i, &j| a[j][j].total_cmp(&a[i][i]));
+    let first = column(&v, order[0]);
+    let second = column(&v, order[1]);
+    SymmetricEigen {
+        values: Vector3::new(a[order[0]][order[0]] as f32, a[order[1]][order[1]] as f32, a[order[2]][order[2]] as f32),
+        vectors: [first, second, first.cross(second)],
+        sweeps,
+        converged,
+    }
+}
+
+// Closed form: the eigenvalues are the roots of the characteristic cubic, found with
+// the trigonometric method, and each eigenvector comes from cross products of the rows
+// of A - λI. Only the upper triangle of m is read.
+pub fn symmetric_eigen_analytic(m : &Matrix3) -> SymmetricEigen
+{
+    let a = symmetric_m3(m);
+    let off = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+    let q = (a[0][0] + a[1][1] + a[2][2]) / 3.;
+    let p = (((a[0][0] - q).powi(2) + (a[1][1] - q).powi(2) + (a[2][2] - q).powi(2) + 2. * off) / 6.).sqrt();
+
+    // a multiple of the identity: every direction is an eigenvector
+    if p <= 1e-12 * q.abs() {
+        return SymmetricEigen {
+            values: Vector3::new(q as f32, q as f32, q as f32),
+            vectors: [Vector3::i_hat(), Vector3::j_hat(), Vector3::k_hat()],
+            sweeps: 0,
+            converged: true,
+        };
+    }
+
+    // B = (A - qI) / p has eigenvalues 2 cos(φ + 2πk/3), with det B / 2 = cos 3φ
+    let b = |i : usize, j : usize| (a[i][j] - if i == j { q } else { 0. }) / p;
+    let det = b(0, 0) * (b(1, 1) * b(2, 2) - b(1, 2) * b(2, 1))
+        - b(0, 1) * (b(1, 0) * b(2, 2) - b(1, 2) * b(2, 0))
+        + b(0, 2) * (b(1, 0) * b(2, 1) - b(1, 1) * b(2, 0));
+    let phi = (det / 2.).clamp(-1., 1.).acos() / 3.;
+    let largest = q + 2. * p * phi.cos();
+    let smallest = q + 2. * p * (phi + 2. * ::std::f64::consts::PI / 3.).cos();
+    let middle = 3. * q - largest - smallest;
+
+    // solve first for whichever outer eigenvalue is further from the middle one, since its
+    // eigenvector is well defined, then find the middle one in the plane perpendicular to it
+    let first_is_largest = largest - middle >= middle - smallest;
+    let outer = if first_is_largest { largest } else { smallest };
+    let outer_vector = null_vector(&a, outer);
+    let middle_vector = null_vector_in_plane(&a, middle, outer_vector);
+
+    let (first, second) = if first_is_largest { (outer_vector, middle_vector) } else { (middle_vector.cross(outer_vector), middle_vector) };
+    SymmetricEigen {
+        values: Vector3::new(largest as f32, middle as f32, smallest as f32),
+        vectors: [first, second, first.cross(second)],
+        sweeps: 0,
+        converged: true,
+    }
+}
+
+fn symmetric_m3(m : &Matrix3) -> M3
+{
+    let mut a = to_m3(m);
+    a[1][0] = a[0][1];
+    a[2][0] = a[0][2];
+    a[2][1] = a[1][2];
+    a
+}
+
+// a unit vector v with (A - λI) v = 0, for an eigenvalue whose eigenspace is a line: the
+// longest cross product of two rows of A - λI is perpendicular to all of them
+fn null_vector(a : &M3, lambda : f64) -> Vector3
+{
+    let row = |i : usize| [a[i][0] - if i == 0 { lambda } else { 0. }, a[i][1] - if i == 1 { lambda } else { 0. }, a[i][2] - if i == 2 { lambda } else { 0. }];
+    let cross = |u : [f64; 3], v : [f64; 3]| [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    let candidates = [cross(row(0), row(1)), cross(row(0), row(2)), cross(row(1), row(2))];
+    let length = |v : &[f64; 3]| v[0] * v[0] + v[1] * v[1] + v[2] * v[2];
+    let best = candidates.iter().fold(candidates[0], |best, c| if length(c) > length(&best) { *c } else { best });
+    let n = length(&best).sqrt();
+    Vector3::new((best[0] / n) as f32, (best[1] / n) as f32, (best[2] / n) as f32)
+}
+
+// the eigenvector for λ perpendicular to `normal`, from the 2x2 restriction of A to that plane
+fn null_vector_in_plane(a : &M3, lambda : f64, normal : Vector3) -> Vector3
+{
+    let (u, w) = normal.orthonormal_basis();
+    let apply = |v : Vector3| {
+        let v = [v.x() as f64, v.y() as f64, v.z() as f64];
+        [a[0][0] * v[0] + a[0][1] * v[1] + a[0][2] * v[2], a[1][0] * v[0] + a[1][1] * v[1] + a[1][2] * v[2], a[2][0] * v[0] + a[2][1] * v[1] + a[2][2] * v[2]]
+    };
+    let dot = |x : [f64; 3], v : Vector3| x[0] * v.x() as f64 + x[1] * v.y() as f64 + x[2] * v.z() as f64;
+    let (au, aw) = (apply(u), apply(w));
+    let (m00, m01, m11) = (dot(au, u) - lambda, dot(aw, u), dot(aw, w) - lambda);
+
+    // (m00, m01) and (m01, m11) are both perpendicular to the eigenvector; use the longer
+    let (x, y) = if m00 * m00 + m01 * m01 >= m01 * m01 + m11 * m11 { (-m01, m00) } else { (-m11, m01) };
+    let n = (x * x + y * y).sqrt();
+    if n == 0. {
+        // λ is repeated, so any direction in the plane will do
+        return u;
+    }
+    (u * (x / n) as f32 + w * (y / n) as f32).unit()
+}
+
+impl Matrix3
+{
+    pub fn svd(&self) -> Svd
+    {
+        svd(self, Convergence::default())
+    }
+
+    pub fn polar(&self) -> Polar
+    {
+        polar(self, Convergence::default())
+    }
+
+    pub fn symmetric_eigen(&self) -> SymmetricEigen
+    {
+        symmetric_eigen(self, Convergence::default())
+    }
+
+    pub fn symmetric_eigen_analytic(&self) -> SymmetricEigen
+    {
+        symmetric_eigen_analytic(self)
+    }
+}
+
+impl AffineMatrix
+{
+    // decompositions of the rotation block; the translation plays no part
+    pub fn svd(&self) -> Svd
+    {
+        Matrix3::from_affine(self).svd()
+    }
+
+    pub fn polar(&self) -> Polar
+    {
+        Matrix3::from_affine(self).polar()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::decomposition::{ Convergence, SymmetricEigen, svd };
+    use ::matrix3::Matrix3;
+    use ::matrices::AffineMatrix;
+    use ::vector3::{ Vec3, Vector3 };
+
+    fn is_orthogonal(m : &Matrix3) -> bool {
+        let p = m.transpose() * *m;
+        (p - Matrix3::identity()).as_row_major_vec().iter().all(|v| v.abs() < 0.0001)
+    }
+
+    #[test]
+    fn svd_rebuilds_sheared_matrix() {
+        let m = AffineMatrix::shear(0.5, -0.2, 0.3, 0., 1.1, 0.4) * AffineMatrix::scale(2., 0.5, 3.) * AffineMatrix::rotation_y(0.8);
+        let d = m.svd();
+        assert!(d.converged);
+        assert!(is_orthogonal(&d.u) && is_orthogonal(&d.v));
+        let s = d.singular_values;
+        assert!(s.x() >= s.y() && s.y() >= s.z() && s.z() >= 0.);
+        assert_approx_eq!(d.to_matrix(), Matrix3::from_affine(&m), epsilon = 0.0001);
+        assert_approx_eq!(s.x() * s.y() * s.z(), m.determinant().abs(), epsilon = 0.0001);
+    }
+
+    #[test]
+    fn svd_of_rotation_and_scale() {
+        let m = AffineMatrix::scale(1., 4., 2.) * AffineMatrix::rotation_axis(Vector3::newi(1, 1, 0), 0.6);
+        assert_approx_eq!(m.svd().singular_values, Vector3::newi(4, 2, 1), epsilon = 0.0001);
+    }
+
+    #[test]
+    fn svd_rank_deficient() {
+        let flat = Matrix3::from_row_major(vec![1., 2., 3., 2., 4., 6., 0., 0., 0.]);
+        let d = flat.svd();
+        assert_approx_eq!(d.singular_values.y(), 0., epsilon = 0.0001);
+        assert!(is_orthogonal(&d.u) && is_orthogonal(&d.v));
+        assert_approx_eq!(d.to_matrix(), flat, epsilon = 0.0001);
+
+        let zero = Matrix3::zero().svd();
+        assert_eq!(zero.singular_values, Vector3::zero());
+        assert!(is_orthogonal(&zero.u));
+    }
+
+    #[test]
+    fn sweep_limit() {
+        let m = Matrix3::from_row_major(vec![4., 1., -2., 3., 5., 1., -1., 2., 6.]);
+        let limited = svd(&m, Convergence { max_sweeps: 1, tolerance: 1e-12 });
+        assert_eq!(limited.sweeps, 1);
+        assert!(!limited.converged);
+        assert!(m.svd().converged);
+    }
+
+    #[test]
+    fn svd_of_nan_does_not_panic() {
+        // inverting a singular matrix is an easy way to end up here
+        let m = AffineMatrix::scale(1., 0., 1.).inverse();
+        let d = m.svd();
+        assert!(!d.converged);
+        assert!(d.singular_values.x().is_nan() || d.singular_values.y().is_nan() || d.singular_values.z().is_nan());
+    }
+
+    #[test]
+    fn polar_splits_rotation_and_stretch() {
+        let stretch = AffineMatrix::scale(2., 3., 0.5);
+        let rotation = AffineMatrix::rotation_axis(Vector3::newi(1, -2, 1), 1.3);
+        let m = stretch * rotation;
+        let p = m.polar();
+        assert_approx_eq!(p.rotation.to_affine(), rotation, epsilon = 0.0001);
+        assert_approx_eq!(p.stretch, p.stretch.transpose(), epsilon = 0.0001);
+        assert_approx_eq!(p.to_matrix(), Matrix3::from_affine(&m), epsilon = 0.0001);
+
+        let mirrored = AffineMatrix::scale(-1., 1., 1.) * rotation;
+        let p = mirrored.polar();
+        assert_approx_eq!(p.rotation.determinant(), -1., epsilon = 0.0001);
+        assert_approx_eq!(p.stretch, Matrix3::identity(), epsilon = 0.0001);
+    }
+
+    fn check_eigen(m : &Matrix3, e : &SymmetricEigen) {
+        let l = e.values;
+        assert!(l.x() >= l.y() && l.y() >= l.z());
+        for (&value, &vector) in [l.x(), l.y(), l.z()].iter().zip(e.vectors.iter()) {
+            assert_approx_eq!(vector.magnitude(), 1., epsilon = 0.0001);
+            assert_approx_eq!(*m * vector, vector * value, epsilon = 0.001);
+        }
+        assert_approx_eq!(e.vectors[0].cross(e.vectors[1]), e.vectors[2], epsilon = 0.0001);
+        assert_approx_eq!(e.to_matrix(), *m, epsilon = 0.001);
+    }
+
+    #[test]
+    fn eigen_of_general_symmetric() {
+        let m = Matrix3::from_row_major(vec![4., 1., -2., 1., 3., 0.5, -2., 0.5, 6.]);
+        for e in &[m.symmetric_eigen(), m.symmetric_eigen_analytic()] {
+            check_eigen(&m, e);
+        }
+        assert_approx_eq!(m.symmetric_eigen().values, m.symmetric_eigen_analytic().values, epsilon = 0.0001);
+        assert!(m.symmetric_eigen().converged);
+    }
+
+    #[test]
+    fn eigen_with_repeated_values() {
+        let r = Matrix3::from_affine(&AffineMatrix::rotation_axis(Vector3::newi(1, 2, 2), 0.7));
+        for &(a, b, c) in &[(5., 1., 1.), (2., 2., -1.), (3., 3., 3.)] {
+            let m = r.transpose() * Matrix3::scale(a, b, c) * r;
+            for e in &[m.symmetric_eigen(), m.symmetric_eigen_analytic()] {
+                check_eigen(&m, e);
+            }
+        }
+    }
+
+    #[test]
+    fn eigen_of_nan_does_not_panic() {
+        let m = Matrix3::from_row_major(vec![1., f32::NAN, 0., f32::NAN, 1., 0., 0., 0., 1.]);
+        assert!(!m.symmetric_eigen().converged);
+        assert!(m.symmetric_eigen_analytic().values.x().is_nan());
+    }
+
+    #[test]
+    fn principal_axis_of_points() {
+        // points spread along (1, 1, 0) with a little spread along z
+        let points : Vec<Vector3> = (-5..6).map(|i| Vector3::new(i as f32, i as f32, (i % 2) as f32 * 0.1)).collect();
+        let covariance = points.iter().fold(Matrix3::zero(), |sum, &p| sum + Matrix3::outer(p, p)) * (1. / points.len() as f32);
+        let axis = covariance.symmetric_eigen_analytic().vectors[0];
+        assert_approx_eq!(axis.dot(Vector3::newi(1, 1, 0).unit()).abs(), 1., epsilon = 0.0001);
+    }
+}
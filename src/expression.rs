@@ -0,0 +1,494 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use ::vector3::{ Vec3, Vector3 };
+use ::matrices::AffineMatrix;
+
+// A small expression language for composing transforms from text, e.g.
+//
+//     translate(1, 2, 3) * rotate_z(90deg) * scale(2)
+//
+// `*` composes matrices with the same meaning as `AffineMatrix`'s `Mul`: the left
+// operand is applied first. A matrix times a vector transforms the vector as a point.
+// Bare angles are radians; `deg`, `rad` and `turn` suffixes convert a literal to radians.
+// A function rejects a unit on any argument but a rotation angle, so `translate(5deg, 0, 0)`
+// is an error rather than a translation by 0.087.
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum Value
+{
+    Number(f32),
+    Vector(Vector3),
+    Matrix(AffineMatrix),
+}
+
+impl Value
+{
+    fn type_name(&self) -> &'static str
+    {
+        match self {
+            Value::Number(_) => "number",
+            Value::Vector(_) => "vector",
+            Value::Matrix(_) => "matrix",
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct ExpressionError
+{
+    pub column : usize,  // one based character column
+    pub message : String,
+}
+
+impl fmt::Display for ExpressionError
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "column {}: {}", self.column, self.message)
+    }
+}
+
+impl Error for ExpressionError {}
+
+pub struct Evaluator
+{
+    variables : HashMap<String, Value>,
+}
+
+impl Evaluator
+{
+    pub fn new() -> Evaluator
+    {
+        let mut variables = HashMap::new();
+        variables.insert("pi".to_string(), Value::Number(::std::f32::consts::PI));
+        Evaluator { variables }
+    }
+
+    pub fn set(&mut self, name : &str, value : Value)
+    {
+        self.variables.insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name : &str) -> Option<Value>
+    {
+        self.variables.get(name).cloned()
+    }
+
+    pub fn variables(&self) -> &HashMap<String, Value>
+    {
+        &self.variables
+    }
+
+    pub fn evaluate(&self, text : &str) -> Result<Value, ExpressionError>
+    {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens: &tokens, index: 0, variables: &self.variables, angle: None };
+        let value = parser.additive()?;
+        match parser.peek() {
+            (Token::End, _) => Ok(value),
+            (_, column) => Err(ExpressionError { column, message: "expected an operator or end of input".to_string() }),
+        }
+    }
+
+    pub fn evaluate_matrix(&self, text : &str) -> Result<AffineMatrix, ExpressionError>
+    {
+        match self.evaluate(text)? {
+            Value::Matrix(m) => Ok(m),
+            other => Err(ExpressionError { column: 1, message: format!("expected a matrix, found a {}", other.type_name()) }),
+        }
+    }
+}
+
+impl Default for Evaluator
+{
+    fn default() -> Evaluator { Evaluator::new() }
+}
+
+// shorthand for evaluating a matrix expression without variables
+pub fn parse_matrix(text : &str) -> Result<AffineMatrix, ExpressionError>
+{
+    Evaluator::new().evaluate_matrix(text)
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+enum Token
+{
+    Number(f32),
+    // a number written with an angle unit, already in radians
+    Angle(f32),
+    Identifier(String),
+    Open,
+    Close,
+    Comma,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    End,
+}
+
+fn tokenize(text : &str) -> Result<Vec<(Token, usize)>, ExpressionError>
+{
+    let chars : Vec<char> = text.chars().collect();
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let column = i + 1;
+        let symbol = match c {
+            '(' => Some(Token::Open),
+            ')' => Some(Token::Close),
+            ',' => Some(Token::Comma),
+            '+' => Some(Token::Plus),
+            '-' => Some(Token::Minus),
+            '*' => Some(Token::Star),
+            '/' => Some(Token::Slash),
+            _ => None,
+        };
+        if let Some(token) = symbol {
+            tokens.push((token, column));
+            i += 1;
+        } else if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let digits = if i + 1 < chars.len() && (chars[i + 1] == '-' || chars[i + 1] == '+') { i + 2 } else { i + 1 };
+                if digits < chars.len() && chars[digits].is_ascii_digit() {
+                    i = digits;
+                    while i < chars.len() && chars[i].is_ascii_digit() { i += 1; }
+                }
+            }
+            let literal : String = chars[start..i].iter().collect();
+            let value = literal.parse::<f32>()
+                .map_err(|_| ExpressionError { column, message: format!("invalid number '{}'", literal) })?;
+
+            let unit_start = i;
+            while i < chars.len() && chars[i].is_ascii_alphabetic() { i += 1; }
+            let unit : String = chars[unit_start..i].iter().collect();
+            let token = match unit.as_str() {
+                "" => Token::Number(value),
+                "rad" => Token::Angle(value),
+                "deg" => Token::Angle(value * ::std::f32::consts::PI / 180.),
+                "turn" => Token::Angle(value * 2. * ::std::f32::consts::PI),
+                _ => return Err(ExpressionError { column: unit_start + 1, message: format!("unknown unit '{}'", unit) }),
+            };
+            tokens.push((token, column));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') { i += 1; }
+            tokens.push((Token::Identifier(chars[start..i].iter().collect()), column));
+        } else {
+            return Err(ExpressionError { column, message: format!("unexpected character '{}'", c) });
+        }
+    }
+
+    tokens.push((Token::End, chars.len() + 1));
+    Ok(tokens)
+}
+
+struct Parser<'a>
+{
+    tokens : &'a [(Token, usize)],
+    index : usize,
+    variables : &'a HashMap<String, Value>,
+    // column of the last angle literal in the function argument being parsed
+    angle : Option<usize>,
+}
+
+impl<'a> Parser<'a>
+{
+    fn peek(&self) -> (Token, usize)
+    {
+        self.tokens[self.index].clone()
+    }
+
+    fn next(&mut self) -> (Token, usize)
+    {
+        let token = self.peek();
+        if token.0 != Token::End {
+            self.index += 1;
+        }
+        token
+    }
+
+    fn expect(&mut self, expected : Token, description : &str) -> Result<(), ExpressionError>
+    {
+        match self.next() {
+            (ref token, _) if *token == expected => Ok(()),
+            (_, column) => Err(ExpressionError { column, message: format!("expected {}", description) }),
+        }
+    }
+
+    fn additive(&mut self) -> Result<Value, ExpressionError>
+    {
+        let mut left = self.multiplicative()?;
+        loop {
+            let (token, column) = self.peek();
+            if token != Token::Plus && token != Token::Minus {
+                return Ok(left);
+            }
+            self.next();
+            let right = self.multiplicative()?;
+            left = match (left, right) {
+                (Value::Number(a), Value::Number(b)) => Value::Number(if token == Token::Plus { a + b } else { a - b }),
+                (Value::Vector(a), Value::Vector(b)) => Value::Vector(if token == Token::Plus { a + b } else { a - b }),
+                (a, b) => return Err(ExpressionError { column, message: format!("cannot add or subtract a {} and a {}", a.type_name(), b.type_name()) }),
+            };
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Value, ExpressionError>
+    {
+        let mut left = self.unary()?;
+        loop {
+            let (token, column) = self.peek();
+            if token != Token::Star && token != Token::Slash {
+                return Ok(left);
+            }
+            self.next();
+            let right = self.unary()?;
+            left = match (token, left, right) {
+                (Token::Star, Value::Number(a), Value::Number(b)) => Value::Number(a * b),
+                (Token::Star, Value::Number(s), Value::Vector(v)) | (Token::Star, Value::Vector(v), Value::Number(s)) => Value::Vector(v * s),
+                (Token::Star, Value::Matrix(a), Value::Matrix(b)) => Value::Matrix(a * b),
+                (Token::Star, Value::Matrix(m), Value::Vector(v)) => Value::Vector(m * v),
+                (Token::Slash, Value::Number(a), Value::Number(b)) => Value::Number(a / b),
+                (Token::Slash, Value::Vector(v), Value::Number(s)) => Value::Vector(v * (1. / s)),
+                (_, a, b) => return Err(ExpressionError { column, message: format!("cannot combine a {} and a {}", a.type_name(), b.type_name()) }),
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<Value, ExpressionError>
+    {
+        let (token, column) = self.peek();
+        if token != Token::Minus {
+            return self.primary();
+        }
+        self.next();
+        match self.unary()? {
+            Value::Number(n) => Ok(Value::Number(-n)),
+            Value::Vector(v) => Ok(Value::Vector(-v)),
+            Value::Matrix(_) => Err(ExpressionError { column, message: "cannot negate a matrix".to_string() }),
+        }
+    }
+
+    fn primary(&mut self) -> Result<Value, ExpressionError>
+    {
+        match self.next() {
+            (Token::Number(n), _) => Ok(Value::Number(n)),
+            (Token::Angle(n), column) => {
+                self.angle = Some(column);
+                Ok(Value::Number(n))
+            },
+            (Token::Open, _) => {
+                let value = self.additive()?;
+                self.expect(Token::Close, "')'")?;
+                Ok(value)
+            },
+            (Token::Identifier(name), column) => {
+                if self.peek().0 != Token::Open {
+                    return self.variables.get(&name).cloned()
+                        .ok_or_else(|| ExpressionError { column, message: format!("unknown variable '{}'", name) });
+                }
+                self.next();
+                let mut args = vec![];
+                let mut angles = vec![];
+                // the call's own arguments are checked by `call`, so they do not leak outward
+                let outer = self.angle.take();
+                if self.peek().0 == Token::Close {
+                    self.next();
+                } else {
+                    loop {
+                        let arg_column = self.peek().1;
+                        args.push((self.additive()?, arg_column));
+                        angles.push(self.angle.take());
+                        match self.next() {
+                            (Token::Comma, _) => continue,
+                            (Token::Close, _) => break,
+                            (_, column) => return Err(ExpressionError { column, message: "expected ',' or ')'".to_string() }),
+                        }
+                    }
+                }
+                self.angle = outer;
+                check_angles(&name, &angles)?;
+                call(&name, &args, column)
+            },
+            (_, column) => Err(ExpressionError { column, message: "expected a number, variable or function call".to_string() }),
+        }
+    }
+}
+
+// `angles` holds, per argument, the column of an angle literal within it
+fn check_angles(name : &str, angles : &[Option<usize>]) -> Result<(), ExpressionError>
+{
+    let angle_argument = match name {
+        "rotate_x" | "rotation_x" | "rotate_y" | "rotation_y" | "rotate_z" | "rotation_z" => Some(0),
+        "rotate" | "rotation" => angles.len().checked_sub(1),
+        _ => None,
+    };
+    for (i, angle) in angles.iter().enumerate() {
+        if let Some(column) = *angle {
+            if Some(i) != angle_argument {
+                return Err(ExpressionError { column, message: format!("argument {} of {}() is not an angle", i + 1, name) });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn call(name : &str, args : &[(Value, usize)], column : usize) -> Result<Value, ExpressionError>
+{
+    let arity = |message : &str| Err(ExpressionError { column, message: format!("{}() takes {}", name, message) });
+    let number = |i : usize| match args[i] {
+        (Value::Number(n), _) => Ok(n),
+        (ref other, column) => Err(ExpressionError { column, message: format!("expected a number, found a {}", other.type_name()) }),
+    };
+    let matrix = |i : usize| match args[i] {
+        (Value::Matrix(m), _) => Ok(m),
+        (ref other, column) => Err(ExpressionError { column, message: format!("expected a matrix, found a {}", other.type_name()) }),
+    };
+    // either three numbers or a single vector
+    let vector = || match args.len() {
+        1 => match args[0] {
+            (Value::Vector(v), _) => Ok(v),
+            (ref other, column) => Err(ExpressionError { column, message: format!("expected a vector, found a {}", other.type_name()) }),
+        },
+        3 => Ok(Vector3::new(number(0)?, number(1)?, number(2)?)),
+        _ => Err(ExpressionError { column, message: format!("{}() takes a vector or three numbers", name) }),
+    };
+
+    match name {
+        "vec" | "vector" => Ok(Value::Vector(vector()?)),
+        "identity" => if args.is_empty() { Ok(Value::Matrix(AffineMatrix::identity())) } else { arity("no arguments") },
+        "translate" | "translation" => {
+            let v = vector()?;
+            Ok(Value::Matrix(AffineMatrix::translation(v.x(), v.y(), v.z())))
+        },
+        "rotate_x" | "rotation_x" => if args.len() == 1 { Ok(Value::Matrix(AffineMatrix::rotation_x(number(0)?))) } else { arity("one angle") },
+        "rotate_y" | "rotation_y" => if args.len() == 1 { Ok(Value::Matrix(AffineMatrix::rotation_y(number(0)?))) } else { arity("one angle") },
+        "rotate_z" | "rotation_z" => if args.len() == 1 { Ok(Value::Matrix(AffineMatrix::rotation_z(number(0)?))) } else { arity("one angle") },
+        "rotate" | "rotation" => {
+            let axis = match args.len() {
+                2 => match args[0] {
+                    (Value::Vector(v), _) => v,
+                    (ref other, column) => return Err(ExpressionError { column, message: format!("expected an axis vector, found a {}", other.type_name()) }),
+                },
+                4 => Vector3::new(number(0)?, number(1)?, number(2)?),
+                _ => return arity("an axis and an angle"),
+            };
+            if axis.magnitude_squared() == 0. {
+                return Err(ExpressionError { column: args[0].1, message: "rotation axis has zero length".to_string() });
+            }
+            Ok(Value::Matrix(AffineMatrix::rotation_axis(axis, number(args.len() - 1)?)))
+        },
+        "scale" => match args.len() {
+            1 => Ok(Value::Matrix(AffineMatrix::uniform_scale(number(0)?))),
+            _ => {
+                let v = vector()?;
+                Ok(Value::Matrix(AffineMatrix::scale(v.x(), v.y(), v.z())))
+            },
+        },
+        "uniform_scale" => if args.len() == 1 { Ok(Value::Matrix(AffineMatrix::uniform_scale(number(0)?))) } else { arity("one factor") },
+        "inverse" => if args.len() == 1 { Ok(Value::Matrix(matrix(0)?.inverse())) } else { arity("one matrix") },
+        "transpose" => if args.len() == 1 { Ok(Value::Matrix(matrix(0)?.transpose())) } else { arity("one matrix") },
+        "determinant" | "det" => if args.len() == 1 { Ok(Value::Number(matrix(0)?.determinant())) } else { arity("one matrix") },
+        _ => Err(ExpressionError { column, message: format!("unknown function '{}'", name) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::vector3::{ Vec3, Vector3 };
+    use ::matrices::AffineMatrix;
+    use ::expression::{ Evaluator, Value, parse_matrix };
+    use std::f32::consts::PI;
+
+    #[test]
+    fn composes_like_mul() {
+        let parsed = parse_matrix("translate(1, 2, 3) * rotate_z(90deg) * scale(2)").unwrap();
+        let built = AffineMatrix::translation(1., 2., 3.) * AffineMatrix::rotation_z(PI / 2.) * AffineMatrix::uniform_scale(2.);
+        assert_eq!(parsed, built);
+    }
+
+    #[test]
+    fn angle_units() {
+        let turn = parse_matrix("rotate_x(0.25turn)").unwrap();
+        let rad = parse_matrix("rotation_x(pi / 2)").unwrap();
+        assert_approx_eq!(turn.apply_vec3(Vector3::j_hat()), rad.apply_vec3(Vector3::j_hat()));
+    }
+
+    #[test]
+    fn units_only_on_angles() {
+        let error = parse_matrix("translate(5deg, 0, 0)").unwrap_err();
+        assert_eq!(error.column, 11);
+        assert_eq!(error.message, "argument 1 of translate() is not an angle");
+        assert_eq!(parse_matrix("scale(2) * rotate(0, 1turn, 1, 1)").unwrap_err().column, 22);
+        assert!(parse_matrix("rotate(0, 0, 1, 1turn / 4)").is_ok());
+        assert!(parse_matrix("rotate(vec(1rad, 0, 0), 1)").is_err());
+
+        // an angle inside a nested rotation belongs to that rotation
+        assert_eq!(parse_matrix("inverse(rotate_z(90deg))").unwrap(), AffineMatrix::rotation_z(PI / 2.).inverse());
+        assert_eq!(parse_matrix("rotate(vec(0, 0, 1), 30deg + 60deg)").unwrap(), AffineMatrix::rotation_axis(Vector3::k_hat(), PI / 2.));
+        // a bare expression may still use units, e.g. to convert
+        assert_eq!(Evaluator::new().evaluate("180deg").unwrap(), Value::Number(PI));
+    }
+
+    #[test]
+    fn variables() {
+        let mut evaluator = Evaluator::new();
+        evaluator.set("height", Value::Number(4.));
+        evaluator.set("base", Value::Matrix(AffineMatrix::translation(1., 0., 0.)));
+        let m = evaluator.evaluate_matrix("base * translate(0, 0, height / 2) * scale(1, 1, -1)").unwrap();
+        assert_eq!(m.apply_vec3(Vector3::zero()), Vector3::newi(1, 0, -2));
+    }
+
+    #[test]
+    fn vectors_and_functions() {
+        let evaluator = Evaluator::new();
+        let value = evaluator.evaluate("inverse(translate(vec(1, 1, 1) * 2)) * vec(2, 3, 4)").unwrap();
+        assert_eq!(value, Value::Vector(Vector3::newi(0, 1, 2)));
+        assert_eq!(evaluator.evaluate("det(scale(2, 3, -1))").unwrap(), Value::Number(-6.));
+
+        let about_diagonal = parse_matrix("rotate(1, 1, 1, 120deg)").unwrap();
+        assert_approx_eq!(about_diagonal.apply_vec3(Vector3::i_hat()), Vector3::j_hat());
+    }
+
+    #[test]
+    fn error_columns() {
+        let error = parse_matrix("translate(1, 2, 3) * spin(4)").unwrap_err();
+        assert_eq!(error.column, 22);
+
+        let error = parse_matrix("scale(2) * offset").unwrap_err();
+        assert_eq!(error.column, 12);
+        assert!(error.message.contains("offset"));
+
+        let error = parse_matrix("rotate_z(90degs)").unwrap_err();
+        assert_eq!(error.column, 12);
+
+        let error = parse_matrix("scale(2) * (1 + scale(3))").unwrap_err();
+        assert_eq!(error.column, 15);
+
+        let error = parse_matrix("scale(2) scale(3)").unwrap_err();
+        assert_eq!(error.column, 10);
+    }
+
+    #[test]
+    fn type_errors() {
+        let error = parse_matrix("rotate_z(vec(1, 0, 0))").unwrap_err();
+        assert_eq!(error.column, 10);
+        assert!(parse_matrix("2 * 3").is_err());
+    }
+}
@@ -0,0 +1,380 @@
+use std::error::Error;
+use std::fmt;
+use ::vector3::{ Vec3, Vector3 };
+use ::matrices::AffineMatrix;
+use ::kdtree::KdTree;
+use ::registration::{ AlignOptions, align };
+
+// Iterative closest point: repeatedly pair each source point with its nearest target point,
+// drop pairs that look like outliers, and solve for the rigid motion that best fits the
+// pairs. Needs a reasonable initial guess; ICP only finds the nearest local minimum.
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum ErrorMetric
+{
+    // distances between paired points, solved exactly with Kabsch
+    PointToPoint,
+    // distances to the target's tangent planes, which slide along flat surfaces and usually
+    // converge in far fewer iterations; needs target normals
+    PointToPlane,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct IcpOptions
+{
+    pub metric : ErrorMetric,
+    pub max_iterations : usize,
+    // stop once the RMS error changes by no more than this between iterations
+    pub tolerance : f32,
+    // pairs further apart than this are ignored
+    pub max_distance : Option<f32>,
+    // the fraction of pairs, worst first, ignored each iteration (trimmed ICP)
+    pub reject_fraction : f32,
+}
+
+impl Default for IcpOptions
+{
+    fn default() -> IcpOptions
+    {
+        IcpOptions { metric: ErrorMetric::PointToPoint, max_iterations: 50, tolerance: 1e-6, max_distance: None, reject_fraction: 0. }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct IcpResult
+{
+    // takes the source cloud onto the target
+    pub transform : AffineMatrix,
+    // The RMS error of the chosen metric over the kept pairs: point distances, or distances
+    // to the target's tangent planes. One entry per iteration, measured before its step,
+    // and a last one for the returned transform.
+    pub errors : Vec<f32>,
+    pub converged : bool,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum IcpError
+{
+    EmptySource,
+    EmptyTarget,
+    // point-to-plane was asked for but the target has no normals
+    MissingNormals,
+    // outlier rejection left too few pairs to solve for a transform
+    TooFewPairs { iteration : usize, pairs : usize },
+}
+
+impl fmt::Display for IcpError
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            IcpError::EmptySource => write!(f, "ICP source cloud is empty"),
+            IcpError::EmptyTarget => write!(f, "ICP target cloud is empty"),
+            IcpError::MissingNormals => write!(f, "point-to-plane ICP needs target normals"),
+            IcpError::TooFewPairs { iteration, pairs } =>
+                write!(f, "ICP iteration {} kept only {} point pairs after outlier rejection", iteration, pairs),
+        }
+    }
+}
+
+impl Error for IcpError {}
+
+// A target cloud indexed for repeated alignment
+pub struct Icp
+{
+    tree : KdTree,
+    normals : Option<Vec<Vector3>>,
+}
+
+impl Icp
+{
+    // Target points with a NaN or infinite coordinate are never paired, as KdTree skips
+    // them; source points like that are dropped from every iteration's pairs.
+    pub fn new(target : Vec<Vector3>) -> Icp
+    {
+        Icp { tree: KdTree::new(target), normals: None }
+    }
+
+    // panics unless there is one normal per target point
+    pub fn with_normals(self, normals : Vec<Vector3>) -> Icp
+    {
+        assert_eq!(normals.len(), self.tree.len(), "expected one normal per target point");
+        Icp { tree: self.tree, normals: Some(normals) }
+    }
+
+    pub fn align(&self, source : &[Vector3], initial : AffineMatrix, options : &IcpOptions) -> Result<IcpResult, IcpError>
+    {
+        if source.is_empty() {
+            return Err(IcpError::EmptySource);
+        }
+        if self.tree.is_empty() {
+            return Err(IcpError::EmptyTarget);
+        }
+        if options.metric == ErrorMetric::PointToPlane && self.normals.is_none() {
+            return Err(IcpError::MissingNormals);
+        }
+
+        let mut transform = initial;
+        let mut errors : Vec<f32> = vec![];
+        let mut converged = false;
+
+        for iteration in 0..options.max_iterations {
+            let pairs = self.pairs(source, &transform, options);
+            if pairs.len() < 3 {
+                return Err(IcpError::TooFewPairs { iteration, pairs: pairs.len() });
+            }
+
+            let moved : Vec<Vector3> = pairs.iter().map(|&(s, _, _)| transform.apply_vec3(source[s])).collect();
+            let rms = self.rms(&moved, &pairs, options.metric);
+            let settled = errors.last().map(|&previous : &f32| (previous - rms).abs() <= options.tolerance).unwrap_or(false);
+            errors.push(rms);
+            if settled {
+                converged = true;
+                break;
+            }
+
+            let step = match options.metric {
+                ErrorMetric::PointToPoint => {
+                    let targets : Vec<Vector3> = pairs.iter().map(|&(_, t, _)| self.tree.points()[t]).collect();
+                    align(&moved, &targets, AlignOptions::default())
+                        .expect("pairs are non-empty, equal in length and unweighted")
+                        .transform
+                },
+                ErrorMetric::PointToPlane => self.plane_step(&moved, &pairs),
+            };
+            transform = transform * step;
+        }
+
+        if !converged {
+            // the last step has not been measured yet
+            let pairs = self.pairs(source, &transform, options);
+            if pairs.len() < 3 {
+                return Err(IcpError::TooFewPairs { iteration: options.max_iterations, pairs: pairs.len() });
+            }
+            let moved : Vec<Vector3> = pairs.iter().map(|&(s, _, _)| transform.apply_vec3(source[s])).collect();
+            errors.push(self.rms(&moved, &pairs, options.metric));
+        }
+
+        Ok(IcpResult { transform, errors, converged })
+    }
+
+    fn rms(&self, moved : &[Vector3], pairs : &[(usize, usize, f32)], metric : ErrorMetric) -> f32
+    {
+        let squared : f32 = match metric {
+            ErrorMetric::PointToPoint => pairs.iter().map(|&(_, _, d2)| d2).sum(),
+            ErrorMetric::PointToPlane => {
+                let normals = self.normals.as_ref().unwrap();
+                moved.iter().zip(pairs).map(|(&p, &(_, t, _))| {
+                    let r = (p - self.tree.points()[t]).dot(normals[t]);
+                    r * r
+                }).sum()
+            },
+        };
+        (squared / pairs.len() as f32).sqrt()
+    }
+
+    // (source index, target index, squared distance) for the pairs that survive rejection
+    fn pairs(&self, source : &[Vector3], transform : &AffineMatrix, options : &IcpOptions) -> Vec<(usize, usize, f32)>
+    {
+        let max_d2 = options.max_distance.map(|d| d * d).unwrap_or(f32::INFINITY);
+        let mut pairs : Vec<(usize, usize, f32)> = source.iter().enumerate()
+            .filter_map(|(i, &p)| self.tree.nearest(transform.apply_vec3(p)).map(|n| (i, n.index, n.distance * n.distance)))
+            .filter(|&(_, _, d2)| d2.is_finite() && d2 <= max_d2)
+            .collect();
+
+        if options.reject_fraction > 0. {
+            pairs.sort_by(|a, b| a.2.total_cmp(&b.2));
+            let keep = ((pairs.len() as f32) * (1. - options.reject_fraction)).ceil() as usize;
+            pairs.truncate(keep);
+        }
+        pairs
+    }
+
+    // Linearizes the rotation as I + [ω]×, making the point-to-plane residual
+    // (p × n)·ω + n·t + (p - q)·n linear in (ω, t), and solves the 6x6 normal equations.
+    fn plane_step(&self, moved : &[Vector3], pairs : &[(usize, usize, f32)]) -> AffineMatrix
+    {
+        let normals = self.normals.as_ref().unwrap();
+        let mut a = [[0f64; 6]; 6];
+        let mut b = [0f64; 6];
+        for (&p, &(_, t, _)) in moved.iter().zip(pairs) {
+            let (q, n) = (self.tree.points()[t], normals[t]);
+            let c = p.cross(n);
+            let row = [c.x(), c.y(), c.z(), n.x(), n.y(), n.z()];
+            let r = (p - q).dot(n) as f64;
+            for i in 0..6 {
+                for j in 0..6 {
+                    a[i][j] += row[i] as f64 * row[j] as f64;
+                }
+                b[i] -= row[i] as f64 * r;
+            }
+        }
+
+        let x = solve6(a, b);
+        let omega = Vector3::new(x[0] as f32, x[1] as f32, x[2] as f32);
+        let angle = omega.magnitude();
+        let rotation = if angle > 0. { AffineMatrix::rotation_axis(omega, angle) } else { AffineMatrix::identity() };
+        rotation * AffineMatrix::translation(x[3] as f32, x[4] as f32, x[5] as f32)
+    }
+}
+
+// Gaussian elimination with partial pivoting. Directions the pairs do not constrain, such
+// as sliding along a single plane, have no usable pivot and are left at zero.
+fn solve6(mut a : [[f64; 6]; 6], mut b : [f64; 6]) -> [f64; 6]
+{
+    let scale = (0..6).map(|i| a[i][i].abs()).fold(0., f64::max);
+    let mut pivots = [None; 6];
+    let mut row = 0;
+    for column in 0..6 {
+        let best = (row..6).max_by(|&i, &j| a[i][column].abs().partial_cmp(&a[j][column].abs()).unwrap());
+        let best = match best {
+            Some(best) if a[best][column].abs() > scale * 1e-9 => best,
+            _ => continue,
+        };
+        a.swap(row, best);
+        b.swap(row, best);
+        let (upper, lower) = a.split_at_mut(row + 1);
+        let pivot = upper[row];
+        for (i, r) in lower.iter_mut().enumerate() {
+            let f = r[column] / pivot[column];
+            for (cell, p) in r.iter_mut().zip(pivot.iter()).skip(column) {
+                *cell -= f * p;
+            }
+            b[row + 1 + i] -= f * b[row];
+        }
+        pivots[column] = Some(row);
+        row += 1;
+    }
+
+    let mut x = [0.; 6];
+    for column in (0..6).rev() {
+        if let Some(r) = pivots[column] {
+            let rest : f64 = (column + 1..6).map(|j| a[r][j] * x[j]).sum();
+            x[column] = (b[r] - rest) / a[r][column];
+        }
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use ::icp::{ Icp, IcpOptions, IcpError, ErrorMetric };
+    use ::matrices::AffineMatrix;
+    use ::vector3::{ Vec3, Vector3 };
+
+    // a bumpy height field, so no rigid motion but the true one lines it up
+    fn surface() -> (Vec<Vector3>, Vec<Vector3>) {
+        let height = |x : f32, y : f32| 0.3 * (x * 0.7).sin() + 0.2 * (y * 1.3).cos() + 0.05 * x * y;
+        let mut points = vec![];
+        let mut normals = vec![];
+        for i in -12..13 {
+            for j in -12..13 {
+                let (x, y) = (i as f32 * 0.25, j as f32 * 0.25);
+                points.push(Vector3::new(x, y, height(x, y)));
+                let dx = 0.21 * (x * 0.7).cos() + 0.05 * y;
+                let dy = -0.26 * (y * 1.3).sin() + 0.05 * x;
+                normals.push(Vector3::new(-dx, -dy, 1.).unit());
+            }
+        }
+        (points, normals)
+    }
+
+    fn misaligned(points : &[Vector3], scale : f32) -> (Vec<Vector3>, AffineMatrix) {
+        let motion = AffineMatrix::rotation_axis(Vector3::newi(1, 2, 3), 0.08 * scale) * AffineMatrix::translation(0.1 * scale, -0.05 * scale, 0.08 * scale);
+        let inverse = motion.inverse();
+        // keep away from the edges, where pairs would drag the source back inside the target
+        let inner = points.iter().filter(|p| p.x().abs() <= 2. && p.y().abs() <= 2.);
+        (inner.map(|&p| inverse.apply_vec3(p)).collect(), motion)
+    }
+
+    #[test]
+    fn point_to_point() {
+        let (target, _) = surface();
+        let (source, motion) = misaligned(&target, 0.4);
+        let result = Icp::new(target).align(&source, AffineMatrix::identity(), &IcpOptions::default()).unwrap();
+        assert!(result.converged);
+        assert!(result.errors[0] > 0.02 && *result.errors.last().unwrap() < 0.001);
+        assert_approx_eq!(result.transform, motion, epsilon = 0.002);
+    }
+
+    #[test]
+    fn point_to_plane_slides_further() {
+        let (target, normals) = surface();
+        let (source, motion) = misaligned(&target, 1.);
+        let icp = Icp::new(target).with_normals(normals);
+
+        let plane = icp.align(&source, AffineMatrix::identity(), &IcpOptions { metric: ErrorMetric::PointToPlane, ..IcpOptions::default() }).unwrap();
+        let point = icp.align(&source, AffineMatrix::identity(), &IcpOptions::default()).unwrap();
+        assert!(plane.converged);
+        assert_approx_eq!(plane.transform, motion, epsilon = 0.002);
+        // point-to-point stalls in a local minimum from this far out
+        assert!(*point.errors.last().unwrap() > 0.05);
+    }
+
+    #[test]
+    fn outliers_rejected() {
+        let (target, _) = surface();
+        let (mut source, motion) = misaligned(&target, 0.4);
+        for i in 0..20 {
+            source[i * 14] = Vector3::new(0., 0., 5. + i as f32);
+        }
+        let options = IcpOptions { reject_fraction: 0.1, max_distance: Some(1.), ..IcpOptions::default() };
+        let result = Icp::new(target).align(&source, AffineMatrix::identity(), &options).unwrap();
+        assert_approx_eq!(result.transform, motion, epsilon = 0.002);
+    }
+
+    #[test]
+    fn non_finite_points_ignored() {
+        let (mut target, _) = surface();
+        let (mut source, motion) = misaligned(&target, 0.4);
+        // on the target's edge, so no hole opens under the source
+        for i in 0..10 {
+            target[i] = Vector3::new(f32::NAN, 0., 0.);
+            source[i * 14] = Vector3::new(0., f32::NAN, f32::INFINITY);
+        }
+        let result = Icp::new(target).align(&source, AffineMatrix::identity(), &IcpOptions::default()).unwrap();
+        assert!(result.converged);
+        assert_approx_eq!(result.transform, motion, epsilon = 0.002);
+    }
+
+    #[test]
+    fn error_history_follows_the_metric() {
+        let (target, normals) = surface();
+        let (source, _) = misaligned(&target, 1.);
+        let icp = Icp::new(target.clone()).with_normals(normals.clone());
+
+        // the last entry measures the returned transform, even when the iterations run out
+        let options = IcpOptions { metric: ErrorMetric::PointToPlane, max_iterations: 3, tolerance: 0., ..IcpOptions::default() };
+        let result = icp.align(&source, AffineMatrix::identity(), &options).unwrap();
+        assert!(!result.converged);
+        assert_eq!(result.errors.len(), 4);
+
+        // the first entry is the point-to-plane residual of the initial pairing
+        let squared : f32 = source.iter().map(|&p| {
+            let t = (0..target.len()).min_by(|&a, &b| (target[a] - p).magnitude().total_cmp(&(target[b] - p).magnitude())).unwrap();
+            (p - target[t]).dot(normals[t]).powi(2)
+        }).sum();
+        assert_approx_eq!(result.errors[0], (squared / source.len() as f32).sqrt(), epsilon = 1e-5);
+    }
+
+    #[test]
+    fn errors() {
+        let (target, _) = surface();
+        let icp = Icp::new(target.clone());
+        assert_eq!(icp.align(&[], AffineMatrix::identity(), &IcpOptions::default()).unwrap_err(), IcpError::EmptySource);
+        assert_eq!(Icp::new(vec![]).align(&target, AffineMatrix::identity(), &IcpOptions::default()).unwrap_err(), IcpError::EmptyTarget);
+        let plane = IcpOptions { metric: ErrorMetric::PointToPlane, ..IcpOptions::default() };
+        assert_eq!(icp.align(&target, AffineMatrix::identity(), &plane).unwrap_err(), IcpError::MissingNormals);
+        let strict = IcpOptions { max_distance: Some(0.), ..IcpOptions::default() };
+        let far : Vec<Vector3> = target.iter().map(|&p| p + Vector3::newi(0, 0, 10)).collect();
+        assert_eq!(icp.align(&far, AffineMatrix::identity(), &strict).unwrap_err(), IcpError::TooFewPairs { iteration: 0, pairs: 0 });
+    }
+}
@@ -0,0 +1,429 @@
+use ::vector3::{ Vec3, Vector3 };
+use ::matrices::AffineMatrix;
+
+// A k-d tree over points, stored implicitly: `order` holds point indices arranged so the
+// median of every range is the splitting node for that range, with the split axis cycling
+// x, y, z by depth. Building is O(n log n) and needs no per-node allocation.
+//
+// Boxes are (min, max) corner pairs, as Mesh::bounding_box returns them.
+
+pub struct KdTree
+{
+    points : Vec<Vector3>,
+    order : Vec<usize>,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Neighbor
+{
+    // index into the points the tree was built from
+    pub index : usize,
+    pub distance : f32,
+}
+
+pub(crate) fn coordinate(v : Vector3, axis : usize) -> f32
+{
+    match axis {
+        0 => v.x(),
+        1 => v.y(),
+        _ => v.z(),
+    }
+}
+
+fn build(points : &[Vector3], order : &mut [usize], depth : usize)
+{
+    if order.len() <= 1 {
+        return;
+    }
+    let axis = depth % 3;
+    let mid = order.len() / 2;
+    order.select_nth_unstable_by(mid, |&a, &b| coordinate(points[a], axis).total_cmp(&coordinate(points[b], axis)));
+    let (left, right) = order.split_at_mut(mid);
+    build(points, left, depth + 1);
+    build(points, &mut right[1..], depth + 1);
+}
+
+impl KdTree
+{
+    // Points with a NaN or infinite coordinate, common in scanner output, keep their index
+    // in `points` but are left out of the tree, so no query ever returns them.
+    pub fn new(points : Vec<Vector3>) -> KdTree
+    {
+        let finite = |p : Vector3| p.x().is_finite() && p.y().is_finite() && p.z().is_finite();
+        let mut order : Vec<usize> = (0..points.len()).filter(|&i| finite(points[i])).collect();
+        build(&points, &mut order, 0);
+        KdTree { points, order }
+    }
+
+    pub fn points(&self) -> &[Vector3]
+    {
+        &self.points
+    }
+
+    pub fn len(&self) -> usize
+    {
+        self.points.len()
+    }
+
+    pub fn is_empty(&self) -> bool
+    {
+        self.points.is_empty()
+    }
+
+    // the closest point to `query`, or None for an empty tree
+    pub fn nearest(&self, query : Vector3) -> Option<Neighbor>
+    {
+        let mut best = None;
+        self.search_nearest(0, self.order.len(), 0, query, &mut best);
+        best.map(|(index, d2) : (usize, f32)| Neighbor { index, distance: d2.sqrt() })
+    }
+
+    fn search_nearest(&self, lo : usize, hi : usize, depth : usize, query : Vector3, best : &mut Option<(usize, f32)>)
+    {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        let index = self.order[mid];
+        let point = self.points[index];
+
+        let d2 = (point - query).magnitude_squared();
+        if best.map(|(_, b)| d2 < b).unwrap_or(true) {
+            *best = Some((index, d2));
+        }
+
+        // search the side of the split holding the query first, then the other side only
+        // if the splitting plane is closer than the best point so far
+        let axis = depth % 3;
+        let diff = coordinate(query, axis) - coordinate(point, axis);
+        let (near, far) = if diff < 0. { ((lo, mid), (mid + 1, hi)) } else { ((mid + 1, hi), (lo, mid)) };
+        self.search_nearest(near.0, near.1, depth + 1, query, best);
+        if best.map(|(_, b)| diff * diff < b).unwrap_or(true) {
+            self.search_nearest(far.0, far.1, depth + 1, query, best);
+        }
+    }
+
+    // up to k points closest to `query`, nearest first
+    pub fn k_nearest(&self, query : Vector3, k : usize) -> Vec<Neighbor>
+    {
+        let mut found = Vec::with_capacity(k + 1);
+        if k > 0 {
+            self.search_k_nearest(0, self.order.len(), 0, query, k, &mut found);
+        }
+        found.into_iter().map(|(index, d2)| Neighbor { index, distance: d2.sqrt() }).collect()
+    }
+
+    // `found` holds (index, squared distance) sorted by distance, at most k long
+    fn search_k_nearest(&self, lo : usize, hi : usize, depth : usize, query : Vector3, k : usize, found : &mut Vec<(usize, f32)>)
+    {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        let index = self.order[mid];
+        let point = self.points[index];
+
+        let d2 = (point - query).magnitude_squared();
+        if found.len() < k || d2 < found[k - 1].1 {
+            let at = found.iter().position(|&(_, f)| d2 < f).unwrap_or(found.len());
+            found.insert(at, (index, d2));
+            found.truncate(k);
+        }
+
+        let axis = depth % 3;
+        let diff = coordinate(query, axis) - coordinate(point, axis);
+        let (near, far) = if diff < 0. { ((lo, mid), (mid + 1, hi)) } else { ((mid + 1, hi), (lo, mid)) };
+        self.search_k_nearest(near.0, near.1, depth + 1, query, k, found);
+        if found.len() < k || diff * diff < found[k - 1].1 {
+            self.search_k_nearest(far.0, far.1, depth + 1, query, k, found);
+        }
+    }
+
+    // every point no further than `radius` from `query`, nearest first
+    pub fn within_radius(&self, query : Vector3, radius : f32) -> Vec<Neighbor>
+    {
+        let mut found = vec![];
+        self.search_radius(0, self.order.len(), 0, query, radius * radius, &mut found);
+        found.sort_by(|a : &Neighbor, b| a.distance.total_cmp(&b.distance));
+        found
+    }
+
+    fn search_radius(&self, lo : usize, hi : usize, depth : usize, query : Vector3, r2 : f32, found : &mut Vec<Neighbor>)
+    {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        let index = self.order[mid];
+        let point = self.points[index];
+
+        let d2 = (point - query).magnitude_squared();
+        if d2 <= r2 {
+            found.push(Neighbor { index, distance: d2.sqrt() });
+        }
+
+        let axis = depth % 3;
+        let diff = coordinate(query, axis) - coordinate(point, axis);
+        if diff <= 0. || diff * diff <= r2 {
+            self.search_radius(lo, mid, depth + 1, query, r2, found);
+        }
+        if diff >= 0. || diff * diff <= r2 {
+            self.search_radius(mid + 1, hi, depth + 1, query, r2, found);
+        }
+    }
+
+    // indices of the points inside the box, boundary included, in ascending order
+    pub fn within_box(&self, min : Vector3, max : Vector3) -> Vec<usize>
+    {
+        let mut found = vec![];
+        self.search_box(0, self.order.len(), 0, min, max, &mut found);
+        found.sort_unstable();
+        found
+    }
+
+    fn search_box(&self, lo : usize, hi : usize, depth : usize, min : Vector3, max : Vector3, found : &mut Vec<usize>)
+    {
+        if lo >= hi {
+            return;
+        }
+        let mid = (lo + hi) / 2;
+        let index = self.order[mid];
+        let point = self.points[index];
+
+        if inside(point, min, max) {
+            found.push(index);
+        }
+
+        let axis = depth % 3;
+        let split = coordinate(point, axis);
+        if coordinate(min, axis) <= split {
+            self.search_box(lo, mid, depth + 1, min, max, found);
+        }
+        if coordinate(max, axis) >= split {
+            self.search_box(mid + 1, hi, depth + 1, min, max, found);
+        }
+    }
+
+    // Queries the tree as if every point had been moved by `transform`, without rebuilding.
+    // Any invertible affine matrix works, shear and non-uniform scale included; those just
+    // make the searches in the tree's space looser. Panics if the matrix is not invertible.
+    pub fn transformed(&self, transform : AffineMatrix) -> TransformedKdTree<'_>
+    {
+        assert!(transform.determinant() != 0., "cannot query through a singular matrix");
+        let inverse = transform.inverse();
+        // no transformed distance shrinks by more than this when taken back by the inverse
+        let stretch = inverse.svd().singular_values.x();
+        TransformedKdTree { tree: self, transform, inverse, stretch }
+    }
+}
+
+fn inside(p : Vector3, min : Vector3, max : Vector3) -> bool
+{
+    p.x() >= min.x() && p.x() <= max.x() && p.y() >= min.y() && p.y() <= max.y() && p.z() >= min.z() && p.z() <= max.z()
+}
+
+// A KdTree seen through a transform: queries are taken back into the tree's space by the
+// inverse, and results are reported in the transformed space. Distances are measured
+// exactly between transformed points; the tree's space is only used to find candidates.
+pub struct TransformedKdTree<'a>
+{
+    tree : &'a KdTree,
+    transform : AffineMatrix,
+    inverse : AffineMatrix,
+    // the largest singular value of the inverse's linear part
+    stretch : f32,
+}
+
+impl<'a> TransformedKdTree<'a>
+{
+    pub fn transform(&self) -> AffineMatrix
+    {
+        self.transform
+    }
+
+    // the point at `index`, moved by the transform
+    pub fn point(&self, index : usize) -> Vector3
+    {
+        self.transform.apply_vec3(self.tree.points[index])
+    }
+
+    pub fn nearest(&self, query : Vector3) -> Option<Neighbor>
+    {
+        self.k_nearest(query, 1).into_iter().next()
+    }
+
+    // The k nearest in the tree's space give an upper bound on the k-th transformed
+    // distance; a radius search with that bound then holds every true answer.
+    pub fn k_nearest(&self, query : Vector3, k : usize) -> Vec<Neighbor>
+    {
+        let candidates = self.tree.k_nearest(self.inverse.apply_vec3(query), k);
+        if candidates.is_empty() {
+            return vec![];
+        }
+        let bound = candidates.iter().map(|n| (self.point(n.index) - query).magnitude()).fold(0., f32::max);
+        let mut found = self.within_radius(query, bound);
+        found.truncate(k);
+        found
+    }
+
+    // nearest first
+    pub fn within_radius(&self, query : Vector3, radius : f32) -> Vec<Neighbor>
+    {
+        // padded so rounding in the transform and its inverse cannot drop boundary points
+        let local = self.inverse.apply_vec3(query);
+        let search = (radius * 1.0001 + query.magnitude() * 1e-5) * self.stretch + local.magnitude() * 1e-5;
+        let mut found : Vec<Neighbor> = self.tree.within_radius(local, search).into_iter()
+            .map(|n| Neighbor { index: n.index, distance: (self.point(n.index) - query).magnitude() })
+            .filter(|n| n.distance <= radius)
+            .collect();
+        found.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        found
+    }
+
+    // The box turns into an oriented box in the tree's space; its bounding box is searched
+    // and each candidate checked against the original box.
+    pub fn within_box(&self, min : Vector3, max : Vector3) -> Vec<usize>
+    {
+        if min.x() > max.x() || min.y() > max.y() || min.z() > max.z() {
+            return vec![];
+        }
+        let corners : Vec<Vector3> = (0..8).map(|i| self.inverse.apply_vec3(Vector3::new(
+            if i & 1 == 0 { min.x() } else { max.x() },
+            if i & 2 == 0 { min.y() } else { max.y() },
+            if i & 4 == 0 { min.z() } else { max.z() }))).collect();
+        let (local_min, local_max) = corners.iter().skip(1).fold((corners[0], corners[0]), |(lo, hi), c| (
+            Vector3::new(lo.x().min(c.x()), lo.y().min(c.y()), lo.z().min(c.z())),
+            Vector3::new(hi.x().max(c.x()), hi.y().max(c.y()), hi.z().max(c.z()))));
+
+        self.tree.within_box(local_min, local_max).into_iter().filter(|&i| inside(self.point(i), min, max)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::kdtree::{ KdTree, Neighbor };
+    use ::matrices::AffineMatrix;
+    use ::vector3::{ Vec3, Vector3 };
+
+    // a deterministic scatter of points, no rand dependency needed
+    fn scatter(count : usize) -> Vec<Vector3> {
+        (0..count).map(|i| {
+            let f = i as f32;
+            Vector3::new((f * 12.9898).sin() * 50., (f * 78.233).sin() * 50., (f * 37.719).sin() * 50.)
+        }).collect()
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let points = scatter(500);
+        let tree = KdTree::new(points.clone());
+        for query in scatter(50).iter().map(|&q| q * 1.1 + Vector3::newi(1, -2, 3)) {
+            let found = tree.nearest(query).unwrap();
+            let brute = points.iter().map(|p| (*p - query).magnitude()).fold(f32::INFINITY, f32::min);
+            assert_eq!(found.distance, brute);
+            assert_eq!((points[found.index] - query).magnitude(), brute);
+        }
+    }
+
+    #[test]
+    fn small_trees() {
+        assert!(KdTree::new(vec![]).nearest(Vector3::zero()).is_none());
+        let single = KdTree::new(vec![Vector3::newi(1, 1, 1)]);
+        assert_eq!(single.nearest(Vector3::zero()).unwrap().index, 0);
+        let duplicates = KdTree::new(vec![Vector3::newi(2, 0, 0); 4]);
+        assert_eq!(duplicates.nearest(Vector3::zero()).unwrap().distance, 2.);
+    }
+
+    #[test]
+    fn non_finite_points_skipped() {
+        let mut points = scatter(50);
+        points[3] = Vector3::new(f32::NAN, 0., 0.);
+        points[17] = Vector3::new(0., f32::INFINITY, 0.);
+        points[30] = Vector3::new(0., 0., f32::NAN);
+        let tree = KdTree::new(points.clone());
+        assert_eq!(tree.len(), 50);
+
+        let everything = tree.within_box(Vector3::new(-1e9, -1e9, -1e9), Vector3::new(1e9, 1e9, 1e9));
+        assert_eq!(everything.len(), 47);
+        assert!(!everything.contains(&3) && !everything.contains(&17) && !everything.contains(&30));
+        assert_eq!(tree.k_nearest(Vector3::zero(), 50).len(), 47);
+        assert_eq!(tree.within_radius(Vector3::zero(), 1e9).len(), 47);
+        assert_eq!(tree.nearest(points[8]).unwrap().index, 8);
+    }
+
+    #[test]
+    fn k_nearest_matches_brute_force() {
+        let points = scatter(400);
+        let tree = KdTree::new(points.clone());
+        for query in scatter(20).iter().map(|&q| q * 0.9 + Vector3::newi(-3, 2, 1)) {
+            let mut brute : Vec<f32> = points.iter().map(|p| (*p - query).magnitude()).collect();
+            brute.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let found : Vec<f32> = tree.k_nearest(query, 7).iter().map(|n| n.distance).collect();
+            assert_eq!(found, &brute[..7]);
+        }
+        assert_eq!(tree.k_nearest(Vector3::zero(), 0), vec![]);
+        assert_eq!(KdTree::new(scatter(3)).k_nearest(Vector3::zero(), 10).len(), 3);
+    }
+
+    #[test]
+    fn radius_and_box_match_brute_force() {
+        let points = scatter(600);
+        let tree = KdTree::new(points.clone());
+
+        let center = Vector3::newi(5, -10, 20);
+        let within = tree.within_radius(center, 25.);
+        let mut indices : Vec<usize> = within.iter().map(|n| n.index).collect();
+        indices.sort_unstable();
+        let brute : Vec<usize> = (0..points.len()).filter(|&i| (points[i] - center).magnitude() <= 25.).collect();
+        assert!(!brute.is_empty());
+        assert_eq!(indices, brute);
+        assert!(within.windows(2).all(|w| w[0].distance <= w[1].distance));
+
+        let (min, max) = (Vector3::newi(-20, -5, -30), Vector3::newi(10, 40, 0));
+        let brute : Vec<usize> = (0..points.len()).filter(|&i| {
+            let p = points[i];
+            p.x() >= min.x() && p.x() <= max.x() && p.y() >= min.y() && p.y() <= max.y() && p.z() >= min.z() && p.z() <= max.z()
+        }).collect();
+        assert!(!brute.is_empty());
+        assert_eq!(tree.within_box(min, max), brute);
+    }
+
+    fn check_transformed(transform : AffineMatrix) {
+        let points = scatter(300);
+        let tree = KdTree::new(points.clone());
+        let moved : Vec<Vector3> = points.iter().map(|&p| transform.apply_vec3(p)).collect();
+        let rebuilt = KdTree::new(moved.clone());
+        let view = tree.transformed(transform);
+
+        let indices = |found : Vec<Neighbor>| found.iter().map(|n| n.index).collect::<Vec<usize>>();
+        for query in scatter(10).iter().map(|&q| transform.apply_vec3(q * 0.8)) {
+            let nearest = view.nearest(query).unwrap();
+            assert_eq!(nearest, rebuilt.nearest(query).unwrap());
+            assert_eq!(view.point(nearest.index), moved[nearest.index]);
+            assert_eq!(view.k_nearest(query, 6), rebuilt.k_nearest(query, 6));
+            let radius = 30. * transform.determinant().abs().cbrt();
+            let within = view.within_radius(query, radius);
+            assert!(within.len() > 1);
+            assert_eq!(indices(within), indices(rebuilt.within_radius(query, radius)));
+        }
+
+        let (min, max) = (transform.apply_vec3(Vector3::newi(-20, -30, -20)), transform.apply_vec3(Vector3::newi(25, 10, 30)));
+        let (min, max) = (Vector3::new(min.x().min(max.x()), min.y().min(max.y()), min.z().min(max.z())),
+                          Vector3::new(min.x().max(max.x()), min.y().max(max.y()), min.z().max(max.z())));
+        assert!(!rebuilt.within_box(min, max).is_empty());
+        assert_eq!(view.within_box(min, max), rebuilt.within_box(min, max));
+    }
+
+    #[test]
+    fn queries_through_similarity() {
+        check_transformed(AffineMatrix::uniform_scale(2.) * AffineMatrix::rotation_axis(Vector3::newi(1, 1, 0), 0.7) * AffineMatrix::translation(100., 0., -50.));
+    }
+
+    #[test]
+    fn queries_through_shear_and_stretch() {
+        check_transformed(AffineMatrix::scale(3., 0.25, 1.) * AffineMatrix::shear(0.8, 0., 0., -0.5, 0.3, 0.) * AffineMatrix::rotation_z(0.4) * AffineMatrix::translation(5., 5., 5.));
+    }
+}
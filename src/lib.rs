@@ -2,7 +2,31 @@
 
 extern crate packed_simd;
 extern crate test;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[macro_use]
+pub mod approx;
+pub mod text;
 pub mod vector3;
 pub mod vector4;
 pub mod simd_vector4;
-pub mod matrices;
\ No newline at end of file
+pub mod matrices;
+pub mod affine3x4;
+pub mod matrix3;
+pub mod decomposition;
+pub mod registration;
+pub mod kdtree;
+pub mod icp;
+pub mod bvh;
+pub mod space;
+pub mod axes;
+pub mod coordinates;
+pub mod bytes;
+pub mod mesh;
+pub mod obj;
+pub mod stl;
+pub mod ply;
+pub mod css;
+pub mod expression;
+#[cfg(feature = "serde")]
+pub mod serde_support;
\ No newline at end of file
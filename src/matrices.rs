@@ -1,7 +1,10 @@
+use std::fmt;
 use std::ops::{ Index, Mul };
+use std::str::FromStr;
 use ::vector3::{ Vec3, Vector3 };
 use ::vector4::Vec4;
 use packed_simd::f32x4 as fvec;
+use ::text::{ ParseError, parse_numbers, format_rows };
 
 pub enum Cell
 {
@@ -28,8 +31,19 @@ impl Cell
     }
 }
 
+// cell names in row-major order, as used by the fields of AffineMatrix
+pub(crate) const CELL_NAMES : [&str; 16] = [
+    "i1", "j1", "k1", "w1",
+    "i2", "j2", "k2", "w2",
+    "i3", "j3", "k3", "w3",
+    "i4", "j4", "k4", "w4",
+];
+
 #[derive(Debug)]
 #[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[repr(C)]
 pub struct AffineMatrix
 {
     i1 : f32, j1 : f32, k1 : f32, w1 : f32,
@@ -92,6 +106,13 @@ impl AffineMatrix
         Vector3::new(a.x(), a.y(), a.z())
     }
 
+    // transforms v as a direction (w = 0), ignoring translation
+    pub fn apply_direction(&self, v : Vector3) -> Vector3
+    {
+        let a = self.apply_affine(fvec::new(v.x(), v.y(), v.z(), 0.));
+        Vector3::new(a.x(), a.y(), a.z())
+    }
+
     pub fn inverse(&self) -> AffineMatrix
     {
         let m = self;
@@ -124,8 +145,8 @@ impl AffineMatrix
 
         let c1v = (fvec::new(m.j2, -m.j1, m.j4, -m.j3) * fvec::new(c5, c5, s5, s5) + fvec::new(-m.k2, m.k1, -m.k4, m.k3) * fvec::new(c4, c4, s4, s4) + fvec::new(m.w2, -m.w1, m.w4, -m.w3) * fvec::new(c3, c3, s3, s3)) * d;
         let c2v = (fvec::new(-m.i2, m.i1, -m.i4, m.i3) * fvec::new(c5, c5, s5, s5) + fvec::new(m.k2, -m.k1, m.k4, -m.k3) * fvec::new(c2, c2, s2, s2) + fvec::new(-m.w2, m.w1, -m.w4, m.w3) * fvec::new(c1, c1, s1, s1)) * d;
-        let c3v = (fvec::new( m.i2, -m.i1, m.i4, -m.i3) * fvec::new(c4, c4, s4, s4) + fvec::new(m.w2, -m.w1, m.w4, -m.w3) * fvec::new(c2, c2, s2, s2) + fvec::new(m.w2, -m.w1, m.w4, -m.w3) * fvec::new(c0, c0, s0, s0)) * d;
-        let c4v = (fvec::new(-m.i2, m.i1, -m.i4, m.i3) * fvec::new(c3, c3, s3, s3) + fvec::new(-m.j2, m.j1, -m.j4, m.j3) * fvec::new(c1, c1, s1, s1) + fvec::new(-m.k2, m.k1, -m.k4, m.k3) * fvec::new(c0, c0, s0, s0)) * d;
+        let c3v = (fvec::new( m.i2, -m.i1, m.i4, -m.i3) * fvec::new(c4, c4, s4, s4) + fvec::new(-m.j2, m.j1, -m.j4, m.j3) * fvec::new(c2, c2, s2, s2) + fvec::new(m.w2, -m.w1, m.w4, -m.w3) * fvec::new(c0, c0, s0, s0)) * d;
+        let c4v = (fvec::new(-m.i2, m.i1, -m.i4, m.i3) * fvec::new(c3, c3, s3, s3) + fvec::new(m.j2, -m.j1, m.j4, -m.j3) * fvec::new(c1, c1, s1, s1) + fvec::new(-m.k2, m.k1, -m.k4, m.k3) * fvec::new(c0, c0, s0, s0)) * d;
 
         AffineMatrix {
             i1: c1v.extract(0), j1: c1v.extract(1), k1: c1v.extract(2), w1: c1v.extract(3),
@@ -135,6 +156,50 @@ impl AffineMatrix
         }
     }
 
+    pub fn determinant(&self) -> f32
+    {
+        let m = self;
+
+        let sa = fvec::new(m.i1, m.i1, m.i1, m.j1) * fvec::new(m.j2, m.k2, m.w2, m.k2);
+        let sb = fvec::new(m.i2, m.i2, m.i2, m.j2) * fvec::new(m.j1, m.k1, m.w1, m.k1);
+        let s = sa - sb;
+
+        let sca = fvec::new(m.j1, m.k1, m.k3, m.j3) * fvec::new(m.w2, m.w2, m.w4, m.w4);
+        let scb = fvec::new(m.j2, m.k2, m.k4, m.j4) * fvec::new(m.w1, m.w1, m.w3, m.w3);
+        let sc = sca - scb;
+
+        let ca = fvec::new(m.j3, m.i3, m.i3, m.i3) * fvec::new(m.k4, m.w4, m.k4, m.j4);
+        let cb = fvec::new(m.j4, m.i4, m.i4, m.i4) * fvec::new(m.k3, m.w3, m.k3, m.j3);
+        let c = ca - cb;
+
+        (fvec::new(s.extract(0), -s.extract(1), s.extract(2), s.extract(3)) * fvec::new(sc.extract(2), sc.extract(3), c.extract(0), c.extract(1))).sum()
+            + sc.extract(1) * c.extract(3) - sc.extract(0) * c.extract(2)
+    }
+
+    // Splits a shear free affine matrix into (translation, rotation, scale) such that
+    // scale(..) * rotation * translation(..) rebuilds it. Mirroring shows up as a negative x scale.
+    // An axis scaled to zero has no direction left to recover: its scale is 0 and its column
+    // of the rotation is NaN, so check the scale before using the rotation of a flattened matrix.
+    pub fn decompose(&self) -> (Vector3, AffineMatrix, Vector3)
+    {
+        let m = self;
+        let translation = Vector3::new(m.w1, m.w2, m.w3);
+
+        let sign = if m.determinant() < 0. { -1. } else { 1. };
+        let x = Vector3::new(m.i1, m.i2, m.i3).magnitude() * sign;
+        let y = Vector3::new(m.j1, m.j2, m.j3).magnitude();
+        let z = Vector3::new(m.k1, m.k2, m.k3).magnitude();
+
+        let rotation = AffineMatrix {
+            i1: m.i1 / x, j1: m.j1 / y, k1: m.k1 / z, w1: 0.,
+            i2: m.i2 / x, j2: m.j2 / y, k2: m.k2 / z, w2: 0.,
+            i3: m.i3 / x, j3: m.j3 / y, k3: m.k3 / z, w3: 0.,
+            i4: 0.,       j4: 0.,       k4: 0.,       w4: 1.,
+        };
+
+        (translation, rotation, Vector3::new(x, y, z))
+    }
+
     pub fn from_row_major(array : Vec<f32>) -> AffineMatrix
     {
         AffineMatrix {
@@ -216,6 +281,22 @@ impl AffineMatrix
         }
     }
 
+    // rotation of theta radians about an arbitrary axis (normalized here)
+    pub fn rotation_axis(axis : Vector3, theta : f32) -> AffineMatrix
+    {
+        let a = axis.unit();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let c = theta.cos();
+        let s = theta.sin();
+        let t = 1. - c;
+        AffineMatrix {
+            i1: t*x*x + c  , j1: t*x*y - s*z, k1: t*x*z + s*y, w1: 0.,
+            i2: t*x*y + s*z, j2: t*y*y + c  , k2: t*y*z - s*x, w2: 0.,
+            i3: t*x*z - s*y, j3: t*y*z + s*x, k3: t*z*z + c  , w3: 0.,
+            i4: 0.         , j4: 0.         , k4: 0.         , w4: 1.,
+        }
+    }
+
     pub fn scale(x : f32, y : f32, z : f32) -> AffineMatrix
     {
         AffineMatrix {
@@ -236,6 +317,76 @@ impl AffineMatrix
         }
     }
 
+    // Each factor moves the first axis in proportion to the second, so `xy` adds
+    // xy * y to x, matching CSS skewX(atan(xy)).
+    pub fn shear(xy : f32, xz : f32, yx : f32, yz : f32, zx : f32, zy : f32) -> AffineMatrix
+    {
+        AffineMatrix {
+            i1: 1., j1: xy, k1: xz, w1: 0.,
+            i2: yx, j2: 1., k2: yz, w2: 0.,
+            i3: zx, j3: zy, k3: 1., w3: 0.,
+            i4: 0., j4: 0., k4: 0., w4: 1.,
+        }
+    }
+
+    // maps the unit axes onto the given axes and the origin onto `origin`
+    pub fn from_basis(x_axis : Vector3, y_axis : Vector3, z_axis : Vector3, origin : Vector3) -> AffineMatrix
+    {
+        AffineMatrix {
+            i1: x_axis.x(), j1: y_axis.x(), k1: z_axis.x(), w1: origin.x(),
+            i2: x_axis.y(), j2: y_axis.y(), k2: z_axis.y(), w2: origin.y(),
+            i3: x_axis.z(), j3: y_axis.z(), k3: z_axis.z(), w3: origin.z(),
+            i4: 0.,         j4: 0.,         k4: 0.,         w4: 1.,
+        }
+    }
+
+    // the first three columns, where the unit axes land
+    pub fn x_axis(&self) -> Vector3
+    {
+        Vector3::new(self.i1, self.i2, self.i3)
+    }
+
+    pub fn y_axis(&self) -> Vector3
+    {
+        Vector3::new(self.j1, self.j2, self.j3)
+    }
+
+    pub fn z_axis(&self) -> Vector3
+    {
+        Vector3::new(self.k1, self.k2, self.k3)
+    }
+
+    // the translation column, where the origin lands
+    pub fn origin(&self) -> Vector3
+    {
+        Vector3::new(self.w1, self.w2, self.w3)
+    }
+
+    // Gram-Schmidt on the rotation block: keeps the x axis direction, then makes y and z
+    // perpendicular to the axes before them. The translation is kept.
+    pub fn orthonormalize(&self) -> AffineMatrix
+    {
+        let x = self.x_axis().unit();
+        let y = (self.y_axis() - x * x.dot(self.y_axis())).unit();
+        let z = (self.z_axis() - x * x.dot(self.z_axis()) - y * y.dot(self.z_axis())).unit();
+        AffineMatrix::from_basis(x, y, z, self.origin())
+    }
+
+    // The nearest orthonormal rotation block, the orthogonal factor of `polar`, which
+    // spreads the correction over all three axes instead of favoring x.
+    pub fn orthonormalize_symmetric(&self) -> AffineMatrix
+    {
+        let r = self.polar().rotation;
+        AffineMatrix::from_basis(r.column(1), r.column(2), r.column(3), self.origin())
+    }
+
+    // whether the rotation block's columns are unit length and perpendicular, within epsilon
+    pub fn is_orthonormal(&self, epsilon : f32) -> bool
+    {
+        let (x, y, z) = (self.x_axis(), self.y_axis(), self.z_axis());
+        [x.dot(x) - 1., y.dot(y) - 1., z.dot(z) - 1., x.dot(y), y.dot(z), z.dot(x)].iter().all(|e| e.abs() <= epsilon)
+    }
+
     pub fn transpose(&self) -> AffineMatrix
     {
         AffineMatrix {
@@ -255,6 +406,16 @@ impl AffineMatrix
             self.i4, self.j4, self.k4, self.w4
         ]
     }
+
+    pub fn as_column_major_vec(&self) -> Vec<f32>
+    {
+        vec![
+            self.i1, self.i2, self.i3, self.i4,
+            self.j1, self.j2, self.j3, self.j4,
+            self.k1, self.k2, self.k3, self.k4,
+            self.w1, self.w2, self.w3, self.w4
+        ]
+    }
 }
 
 impl Index<Cell> for AffineMatrix
@@ -319,6 +480,25 @@ impl Mul<fvec> for AffineMatrix
     }
 }
 
+// `{}` writes "[1 0 0 5; 0 1 0 0; 0 0 1 0; 0 0 0 1]" and `{:#}` puts each row on its own line
+impl fmt::Display for AffineMatrix
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        format_rows(&self.as_row_major_vec(), 4, f)
+    }
+}
+
+// reads sixteen numbers in row-major order, in either Display form
+impl FromStr for AffineMatrix
+{
+    type Err = ParseError;
+    fn from_str(s : &str) -> Result<AffineMatrix, ParseError>
+    {
+        parse_numbers(s, 16, "AffineMatrix").map(AffineMatrix::from_row_major)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use ::vector3::{Vec3, Vector3};
@@ -330,18 +510,6 @@ mod tests {
     const C : f32 = 0.5403023058681398; // cos(1)
     const S : f32 = 0.8414709848078965; // sin(1)
 
-    const TOLERANCE : f32 = 0.000001;
-
-    macro_rules!  assert_aprox{
-        ( $ left : expr , $ right : expr ) => (
-        {
-            match ( & ( $ left ) , & ( $ right ) ) {
-                ( left_val , right_val ) => {
-                    if ! ( (* left_val - * right_val).magnitude_squared() < TOLERANCE ) { panic!("assertion failed: `(left == right)` (left: `{:?}`, right: `{:?}`)", * left_val , * right_val ) }
-                } }
-        } )
-    }
-
     #[test]
     fn inverse_affine_identity() {
         let identity = AffineMatrix::identity();
@@ -378,14 +546,120 @@ mod tests {
 
         // assert that the result is <cos(1),sin(1),0>
         let expected = Vector3::new(C, S, 0.0);
-        assert_aprox!(rotated, expected);
+        assert_approx_eq!(rotated, expected);
 
         // use the 'revert' Matrix to undo the rotation
         let returned = revert.apply_vec3(rotated);
 
         // assert that the result is back to <1,0,0>, within a tolerance
         let i = Vector3::i_hat();
-        assert_aprox!(returned, i);
+        assert_approx_eq!(returned, i);
+    }
+
+    #[test]
+    fn inverse_general_affine() {
+        let m = AffineMatrix::from_row_major(
+            vec![2., 1., 0., 3.,
+                 0.5,3., 1.,-1.,
+                 0., 1., 4., 2.,
+                 0., 0., 0., 1.]);
+        let inverse = m.inverse();
+        let v = Vector3::new(1., -2., 0.5);
+
+        // undoing the transform returns the original point
+        let returned = inverse.apply_vec3(m.apply_vec3(v));
+        assert_approx_eq!(returned, v);
+    }
+
+    #[test]
+    fn determinant_identity() {
+        assert_eq!(AffineMatrix::identity().determinant(), 1.);
+    }
+
+    #[test]
+    fn determinant_scale_and_reflection() {
+        assert_eq!(AffineMatrix::scale(2., 3., 4.).determinant(), 24.);
+        assert_eq!(AffineMatrix::scale(-1., 1., 1.).determinant(), -1.);
+        let rotate = AffineMatrix::rotation_z(1.) * AffineMatrix::translation(3., 2., 1.);
+        assert_approx_eq!(rotate.determinant(), 1.);
+    }
+
+    #[test]
+    fn decompose_rebuilds() {
+        let m = AffineMatrix::scale(2., 3., 4.) * AffineMatrix::rotation_y(0.7) * AffineMatrix::translation(1., -2., 5.);
+        let (t, r, s) = m.decompose();
+        assert_approx_eq!(t, Vector3::newi(1, -2, 5));
+        assert_approx_eq!(s, Vector3::newi(2, 3, 4));
+        assert_approx_eq!(r.determinant(), 1.);
+
+        let rebuilt = AffineMatrix::scale(s.x(), s.y(), s.z()) * r * AffineMatrix::translation(t.x(), t.y(), t.z());
+        assert_approx_eq!(rebuilt, m);
+    }
+
+    #[test]
+    fn shear_moves_axes() {
+        let m = AffineMatrix::shear(2., 0., 0., 0., 0., 3.);
+        assert_eq!(m.apply_vec3(Vector3::newi(0, 1, 0)), Vector3::newi(2, 1, 3));
+        assert_eq!(m.apply_vec3(Vector3::newi(1, 0, 1)), Vector3::newi(1, 0, 1));
+        assert_eq!(AffineMatrix::shear(0., 0., 0., 0., 0., 0.), AffineMatrix::identity());
+    }
+
+    #[test]
+    fn basis_round_trip() {
+        let (x, y, z, o) = (Vector3::newi(0, 1, 0), Vector3::newi(-1, 0, 0), Vector3::newi(0, 0, 2), Vector3::newi(4, 5, 6));
+        let frame = AffineMatrix::from_basis(x, y, z, o);
+        assert_eq!((frame.x_axis(), frame.y_axis(), frame.z_axis(), frame.origin()), (x, y, z, o));
+        assert_eq!(frame.apply_vec3(Vector3::i_hat()), x + o);
+        assert_approx_eq!(frame, AffineMatrix::scale(1., 1., 2.) * AffineMatrix::rotation_z(PI / 2.) * AffineMatrix::translation(4., 5., 6.));
+    }
+
+    #[test]
+    fn orthonormalize_drifted_rotation() {
+        let step = AffineMatrix::rotation_axis(Vector3::newi(1, 2, 3), 0.001);
+        let mut drifted = AffineMatrix::translation(1., 2., 3.);
+        for _ in 0..5000 {
+            drifted = drifted * step * AffineMatrix::uniform_scale(1.00001);
+        }
+        assert!(!drifted.is_orthonormal(0.01));
+
+        for fixed in &[drifted.orthonormalize(), drifted.orthonormalize_symmetric()] {
+            assert!(fixed.is_orthonormal(0.00001));
+            assert_approx_eq!(fixed.determinant(), 1.);
+            assert_eq!(fixed.origin(), drifted.origin());
+        }
+        assert_approx_eq!(drifted.orthonormalize().x_axis(), drifted.x_axis().unit());
+    }
+
+    #[test]
+    fn symmetric_orthonormalize_is_nearest() {
+        let skewed = AffineMatrix::shear(0.1, 0., 0., 0., 0., 0.);
+        let r = skewed.orthonormalize_symmetric();
+        // the correction is shared between x and y rather than all put on y
+        assert_approx_eq!(r.x_axis().dot(Vector3::i_hat()), r.y_axis().dot(Vector3::j_hat()));
+        assert_approx_eq!(AffineMatrix::rotation_z(0.4).orthonormalize_symmetric(), AffineMatrix::rotation_z(0.4));
+    }
+
+    #[test]
+    fn decompose_mirror() {
+        let (_, r, s) = AffineMatrix::scale(1., -1., 1.).decompose();
+        assert_eq!(s, Vector3::newi(-1, 1, 1));
+        assert_approx_eq!(r.determinant(), 1.);
+    }
+
+    #[test]
+    fn decompose_flattened() {
+        let (t, r, s) = (AffineMatrix::scale(2., 0., 3.) * AffineMatrix::translation(1., 2., 3.)).decompose();
+        assert_eq!(t, Vector3::newi(1, 2, 3));
+        assert_eq!(s, Vector3::newi(2, 0, 3));
+        assert!(r.y_axis().x().is_nan() && r.y_axis().y().is_nan() && r.y_axis().z().is_nan());
+        assert_eq!(r.x_axis(), Vector3::i_hat());
+        assert_eq!(r.z_axis(), Vector3::k_hat());
+    }
+
+    #[test]
+    fn direction_ignores_translation() {
+        let t = AffineMatrix::translation(5., 6., 7.);
+        assert_eq!(t.apply_direction(Vector3::i_hat()), Vector3::i_hat());
     }
 
     #[bench]
@@ -451,6 +725,23 @@ mod tests {
         assert_eq!(i, i2);
     }
 
+    #[test]
+    fn rotation_axis_matches_primitives() {
+        let about_z = AffineMatrix::rotation_axis(Vector3::k_hat() * 3., 1.);
+        let expected = AffineMatrix::rotation_z(1.);
+        assert_approx_eq!(about_z, expected);
+
+        // a third of a turn about the diagonal cycles the axes
+        let cycle = AffineMatrix::rotation_axis(Vector3::identity(), 2. * PI / 3.);
+        assert_approx_eq!(cycle.apply_vec3(Vector3::i_hat()), Vector3::j_hat());
+    }
+
+    #[test]
+    fn column_major_vec_round_trip() {
+        let values : Vec<f32> = (0..16).map(|i| i as f32).collect();
+        assert_eq!(AffineMatrix::from_column_major(values.clone()).as_column_major_vec(), values);
+    }
+
     #[test]
     fn translate() {
         // start at <1,0,0>
@@ -461,7 +752,7 @@ mod tests {
 
         let should_be_j = t.apply_vec3(i);
 
-        assert_aprox!(should_be_j, Vector3::j_hat());
+        assert_approx_eq!(should_be_j, Vector3::j_hat());
     }
 
     #[test]
@@ -473,7 +764,23 @@ mod tests {
 
         let should_be_2j = t * i;
 
-        assert_aprox!(should_be_2j, Vector3::j_hat() * 2.0);
+        assert_approx_eq!(should_be_2j, Vector3::j_hat() * 2.0);
+    }
+
+    #[test]
+    fn display_forms() {
+        let m = AffineMatrix::translation(10., -2., 0.5);
+        assert_eq!(m.to_string(), "[  1   0   0  10;   0   1   0  -2;   0   0   1 0.5;   0   0   0   1]");
+        assert_eq!(format!("{:#.1}", AffineMatrix::uniform_scale(2.)),
+            "[2.0 0.0 0.0 0.0\n 0.0 2.0 0.0 0.0\n 0.0 0.0 2.0 0.0\n 0.0 0.0 0.0 1.0]");
+    }
+
+    #[test]
+    fn parse_display_round_trip() {
+        let m = AffineMatrix::rotation_y(0.3) * AffineMatrix::translation(1., 2., 3.);
+        assert_eq!(m.to_string().parse::<AffineMatrix>().unwrap(), m);
+        assert_eq!(format!("{:#}", m).parse::<AffineMatrix>().unwrap(), m);
+        assert!("[1 0 0 0; 0 1 0 0]".parse::<AffineMatrix>().is_err());
     }
 
     #[test]
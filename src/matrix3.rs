@@ -0,0 +1,328 @@
+use std::fmt;
+use std::ops::{ Add, Sub, Mul };
+use std::str::FromStr;
+use ::vector3::{ Vec3, Vector3 };
+use ::matrices::AffineMatrix;
+use ::text::{ ParseError, parse_numbers, format_rows };
+
+// A 3x3 matrix for linear maps with no translation: normal matrices, inertia tensors and
+// covariances. Multiplication follows AffineMatrix, so `a * b` applies a, then b.
+
+pub(crate) const CELL_NAMES : [&str; 9] = [
+    "i1", "j1", "k1",
+    "i2", "j2", "k2",
+    "i3", "j3", "k3",
+];
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[repr(C)]
+pub struct Matrix3
+{
+    i1 : f32, j1 : f32, k1 : f32,
+    i2 : f32, j2 : f32, k2 : f32,
+    i3 : f32, j3 : f32, k3 : f32
+}
+
+impl Matrix3
+{
+    pub fn from_row_major(array : Vec<f32>) -> Matrix3
+    {
+        Matrix3 {
+            i1: array[0], j1: array[1], k1: array[2],
+            i2: array[3], j2: array[4], k2: array[5],
+            i3: array[6], j3: array[7], k3: array[8],
+        }
+    }
+
+    pub fn from_column_major(array : Vec<f32>) -> Matrix3
+    {
+        Matrix3 {
+            i1: array[0], j1: array[3], k1: array[6],
+            i2: array[1], j2: array[4], k2: array[7],
+            i3: array[2], j3: array[5], k3: array[8],
+        }
+    }
+
+    pub fn zero() -> Matrix3
+    {
+        Matrix3::from_row_major(vec![0.0;9])
+    }
+
+    pub fn identity() -> Matrix3
+    {
+        Matrix3::scale(1., 1., 1.)
+    }
+
+    pub fn scale(x : f32, y : f32, z : f32) -> Matrix3
+    {
+        Matrix3 {
+            i1: x,  j1: 0., k1: 0.,
+            i2: 0., j2: y,  k2: 0.,
+            i3: 0., j3: 0., k3: z,
+        }
+    }
+
+    // a bᵀ, the building block of covariance and inertia sums
+    pub fn outer(a : Vector3, b : Vector3) -> Matrix3
+    {
+        Matrix3 {
+            i1: a.x() * b.x(), j1: a.x() * b.y(), k1: a.x() * b.z(),
+            i2: a.y() * b.x(), j2: a.y() * b.y(), k2: a.y() * b.z(),
+            i3: a.z() * b.x(), j3: a.z() * b.y(), k3: a.z() * b.z(),
+        }
+    }
+
+    // the upper-left block of m, dropping its translation
+    pub fn from_affine(m : &AffineMatrix) -> Matrix3
+    {
+        let c = m.as_row_major_vec();
+        Matrix3::from_row_major(vec![c[0], c[1], c[2], c[4], c[5], c[6], c[8], c[9], c[10]])
+    }
+
+    // the matrix that carries m's surface normals: the inverse-transpose of its linear part
+    pub fn normal_matrix(m : &AffineMatrix) -> Matrix3
+    {
+        Matrix3::from_affine(m).inverse().transpose()
+    }
+
+    pub fn to_affine(&self) -> AffineMatrix
+    {
+        let m = self;
+        AffineMatrix::from_row_major(vec![
+            m.i1, m.j1, m.k1, 0.,
+            m.i2, m.j2, m.k2, 0.,
+            m.i3, m.j3, m.k3, 0.,
+            0.,   0.,   0.,   1.])
+    }
+
+    pub fn as_row_major_vec(&self) -> Vec<f32>
+    {
+        vec![self.i1, self.j1, self.k1, self.i2, self.j2, self.k2, self.i3, self.j3, self.k3]
+    }
+
+    pub fn as_column_major_vec(&self) -> Vec<f32>
+    {
+        vec![self.i1, self.i2, self.i3, self.j1, self.j2, self.j3, self.k1, self.k2, self.k3]
+    }
+
+    // row (1, 2, 3)
+    pub fn row(&self, row : u8) -> Vector3
+    {
+        match row {
+            1 => Vector3::new(self.i1, self.j1, self.k1),
+            2 => Vector3::new(self.i2, self.j2, self.k2),
+            3 => Vector3::new(self.i3, self.j3, self.k3),
+            _ => panic!("Matrix Index out of bounds"),
+        }
+    }
+
+    // column (1, 2, 3)
+    pub fn column(&self, column : u8) -> Vector3
+    {
+        match column {
+            1 => Vector3::new(self.i1, self.i2, self.i3),
+            2 => Vector3::new(self.j1, self.j2, self.j3),
+            3 => Vector3::new(self.k1, self.k2, self.k3),
+            _ => panic!("Matrix Index out of bounds"),
+        }
+    }
+
+    // m * self, like AffineMatrix::multiply
+    pub fn multiply(&self, m : Matrix3) -> Matrix3
+    {
+        let (c1, c2, c3) = (self.column(1), self.column(2), self.column(3));
+        let (r1, r2, r3) = (m.row(1), m.row(2), m.row(3));
+
+        Matrix3 {
+            i1: r1.dot(c1), j1: r1.dot(c2), k1: r1.dot(c3),
+            i2: r2.dot(c1), j2: r2.dot(c2), k2: r2.dot(c3),
+            i3: r3.dot(c1), j3: r3.dot(c2), k3: r3.dot(c3),
+        }
+    }
+
+    pub fn apply_vec3(&self, v : Vector3) -> Vector3
+    {
+        Vector3::new(self.row(1).dot(v), self.row(2).dot(v), self.row(3).dot(v))
+    }
+
+    pub fn transpose(&self) -> Matrix3
+    {
+        Matrix3::from_column_major(self.as_row_major_vec())
+    }
+
+    pub fn trace(&self) -> f32
+    {
+        self.i1 + self.j2 + self.k3
+    }
+
+    pub fn determinant(&self) -> f32
+    {
+        self.row(1).dot(self.row(2).cross(self.row(3)))
+    }
+
+    // The adjugate over the determinant. A singular matrix gives infinite or NaN cells,
+    // as AffineMatrix::inverse does.
+    pub fn inverse(&self) -> Matrix3
+    {
+        let (r1, r2, r3) = (self.row(1), self.row(2), self.row(3));
+        let (a, b, c) = (r2.cross(r3), r3.cross(r1), r1.cross(r2));
+        let d = 1. / r1.dot(a);
+
+        // the cross products are the columns of the inverse
+        Matrix3 {
+            i1: a.x() * d, j1: b.x() * d, k1: c.x() * d,
+            i2: a.y() * d, j2: b.y() * d, k2: c.y() * d,
+            i3: a.z() * d, j3: b.z() * d, k3: c.z() * d,
+        }
+    }
+
+    fn zip(&self, m : Matrix3, f : fn(f32, f32) -> f32) -> Matrix3
+    {
+        Matrix3::from_row_major(self.as_row_major_vec().iter().zip(m.as_row_major_vec()).map(|(&a, b)| f(a, b)).collect())
+    }
+}
+
+impl From<Matrix3> for AffineMatrix
+{
+    fn from(m : Matrix3) -> AffineMatrix
+    {
+        m.to_affine()
+    }
+}
+
+impl Mul for Matrix3
+{
+    type Output = Matrix3;
+    fn mul(self, m : Matrix3) -> Matrix3 {
+        self.multiply(m)
+    }
+}
+
+impl Mul<Vector3> for Matrix3
+{
+    type Output = Vector3;
+    fn mul(self, v : Vector3) -> Vector3 {
+        self.apply_vec3(v)
+    }
+}
+
+impl Mul<f32> for Matrix3
+{
+    type Output = Matrix3;
+    fn mul(self, s : f32) -> Matrix3 {
+        Matrix3::from_row_major(self.as_row_major_vec().iter().map(|v| v * s).collect())
+    }
+}
+
+impl Add for Matrix3
+{
+    type Output = Matrix3;
+    fn add(self, m : Matrix3) -> Matrix3 {
+        self.zip(m, |a, b| a + b)
+    }
+}
+
+impl Sub for Matrix3
+{
+    type Output = Matrix3;
+    fn sub(self, m : Matrix3) -> Matrix3 {
+        self.zip(m, |a, b| a - b)
+    }
+}
+
+// same forms as AffineMatrix, "[1 0 0; 0 1 0; 0 0 1]" or one row per line with `{:#}`
+impl fmt::Display for Matrix3
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        format_rows(&self.as_row_major_vec(), 3, f)
+    }
+}
+
+impl FromStr for Matrix3
+{
+    type Err = ParseError;
+    fn from_str(s : &str) -> Result<Matrix3, ParseError>
+    {
+        parse_numbers(s, 9, "Matrix3").map(Matrix3::from_row_major)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::matrix3::Matrix3;
+    use ::matrices::AffineMatrix;
+    use ::vector3::{ Vec3, Vector3 };
+
+    fn sample() -> Matrix3 {
+        Matrix3::from_row_major(vec![2., 1., 0., -1., 3., 2., 0., 1., 4.])
+    }
+
+    #[test]
+    fn affine_block_round_trip() {
+        let m = AffineMatrix::rotation_y(0.6) * AffineMatrix::scale(1., 2., 3.);
+        let with_translation = m * AffineMatrix::translation(4., 5., 6.);
+        assert_eq!(Matrix3::from_affine(&with_translation).to_affine(), m);
+        assert_eq!(AffineMatrix::from(Matrix3::identity()), AffineMatrix::identity());
+    }
+
+    #[test]
+    fn multiply_matches_affine_matrix() {
+        let a = AffineMatrix::rotation_x(0.3) * AffineMatrix::scale(2., 1., 1.);
+        let b = AffineMatrix::rotation_z(1.2);
+        let product = Matrix3::from_affine(&a) * Matrix3::from_affine(&b);
+        assert_approx_eq!(product.to_affine(), a * b);
+
+        let v = Vector3::newi(1, -2, 3);
+        assert_approx_eq!(Matrix3::from_affine(&a) * v, a.apply_direction(v));
+    }
+
+    #[test]
+    fn determinant_and_inverse() {
+        let m = sample();
+        assert_approx_eq!(m.determinant(), 24.);
+        assert_approx_eq!(m * m.inverse(), Matrix3::identity());
+        assert_approx_eq!(m.inverse().to_affine(), m.to_affine().inverse());
+        assert_eq!(m.transpose().transpose(), m);
+        assert_eq!(m.trace(), 9.);
+    }
+
+    #[test]
+    fn normal_matrix_keeps_normals_perpendicular() {
+        let m = AffineMatrix::scale(3., 1., 1.) * AffineMatrix::rotation_z(0.5);
+        let tangent = Vector3::newi(1, 1, 0);
+        let normal = Vector3::newi(1, -1, 0);
+        let n = Matrix3::normal_matrix(&m) * normal;
+        assert_approx_eq!(n.dot(m.apply_direction(tangent)), 0.);
+    }
+
+    #[test]
+    fn covariance_sums() {
+        let points = [Vector3::newi(1, 0, 0), Vector3::newi(-1, 0, 0), Vector3::newi(0, 2, 0)];
+        let covariance = points.iter().fold(Matrix3::zero(), |sum, &p| sum + Matrix3::outer(p, p)) * 0.5;
+        assert_eq!(covariance, Matrix3::scale(1., 2., 0.));
+        assert_eq!(covariance - covariance, Matrix3::zero());
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let m = sample();
+        assert_eq!(m.to_string(), "[ 2  1  0; -1  3  2;  0  1  4]");
+        assert_eq!(m.to_string().parse::<Matrix3>().unwrap(), m);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn row_out_of_bounds() {
+        sample().row(4);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of bounds")]
+    fn column_out_of_bounds() {
+        sample().column(0);
+    }
+}
@@ -0,0 +1,233 @@
+use ::vector3::{ Vec3, Vector3 };
+use ::matrices::AffineMatrix;
+use ::matrix3::Matrix3;
+
+// Triangle mesh: every three indices form one counter-clockwise triangle.
+// Normals and tangents, when present, have one entry per position.
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Mesh
+{
+    positions : Vec<Vector3>,
+    normals : Option<Vec<Vector3>>,
+    tangents : Option<Vec<Vector3>>,
+    indices : Vec<u32>,
+}
+
+impl Mesh
+{
+    pub fn new(positions : Vec<Vector3>, indices : Vec<u32>) -> Mesh
+    {
+        if !indices.len().is_multiple_of(3) {
+            panic!("Mesh indices must come in triangles");
+        }
+        if indices.iter().any(|&i| i as usize >= positions.len()) {
+            panic!("Mesh index out of bounds");
+        }
+        Mesh { positions, normals: None, tangents: None, indices }
+    }
+
+    pub fn with_normals(&self, normals : Vec<Vector3>) -> Mesh
+    {
+        if normals.len() != self.positions.len() {
+            panic!("Mesh needs exactly one normal per position");
+        }
+        Mesh { normals: Some(normals), ..self.clone() }
+    }
+
+    pub fn with_tangents(&self, tangents : Vec<Vector3>) -> Mesh
+    {
+        if tangents.len() != self.positions.len() {
+            panic!("Mesh needs exactly one tangent per position");
+        }
+        Mesh { tangents: Some(tangents), ..self.clone() }
+    }
+
+    pub fn positions(&self) -> &[Vector3] { &self.positions }
+    pub fn normals(&self) -> Option<&[Vector3]> { self.normals.as_ref().map(|n| &n[..]) }
+    pub fn tangents(&self) -> Option<&[Vector3]> { self.tangents.as_ref().map(|t| &t[..]) }
+    pub fn indices(&self) -> &[u32] { &self.indices }
+
+    pub fn triangle_count(&self) -> usize
+    {
+        self.indices.len() / 3
+    }
+
+    pub fn triangles(&self) -> Vec<[Vector3; 3]>
+    {
+        self.indices.chunks(3)
+            .map(|t| [self.positions[t[0] as usize], self.positions[t[1] as usize], self.positions[t[2] as usize]])
+            .collect()
+    }
+
+    // Positions are transformed as points, tangents as directions and normals by the
+    // inverse-transpose. Mirroring transforms flip the winding so faces stay outward.
+    pub fn apply(&self, m : &AffineMatrix) -> Mesh
+    {
+        let positions = self.positions.iter().map(|&p| m.apply_vec3(p)).collect();
+
+        let normal_matrix = Matrix3::normal_matrix(m);
+        let normals = self.normals.as_ref()
+            .map(|n| n.iter().map(|&v| unit_or_zero(normal_matrix.apply_vec3(v))).collect());
+
+        let tangents = self.tangents.as_ref()
+            .map(|t| t.iter().map(|&v| unit_or_zero(m.apply_direction(v))).collect());
+
+        let indices = if m.determinant() < 0. {
+            self.indices.chunks(3).flat_map(|t| vec![t[0], t[2], t[1]]).collect()
+        } else {
+            self.indices.clone()
+        };
+
+        Mesh { positions, normals, tangents, indices }
+    }
+
+    // Per-vertex normals averaged from adjacent faces, weighted by face area
+    pub fn with_smooth_normals(&self) -> Mesh
+    {
+        let mut normals = vec![Vector3::zero(); self.positions.len()];
+        for t in self.indices.chunks(3) {
+            let face = self.face_normal(t);
+            for &i in t {
+                normals[i as usize] = normals[i as usize] + face;
+            }
+        }
+        self.with_normals(normals.into_iter().map(unit_or_zero).collect())
+    }
+
+    // Splits shared vertices so every triangle carries its own face normal
+    pub fn with_flat_normals(&self) -> Mesh
+    {
+        let mut positions = Vec::with_capacity(self.indices.len());
+        let mut normals = Vec::with_capacity(self.indices.len());
+        for t in self.indices.chunks(3) {
+            let face = unit_or_zero(self.face_normal(t));
+            for &i in t {
+                positions.push(self.positions[i as usize]);
+                normals.push(face);
+            }
+        }
+        let tangents = self.tangents.as_ref()
+            .map(|tangents| self.indices.iter().map(|&i| tangents[i as usize]).collect());
+        let indices = (0..self.indices.len() as u32).collect();
+
+        Mesh { positions, normals: Some(normals), tangents, indices }
+    }
+
+    // (min, max) corners of the axis aligned bounding box, None for an empty mesh
+    pub fn bounding_box(&self) -> Option<(Vector3, Vector3)>
+    {
+        let first = match self.positions.first() {
+            Some(&p) => p,
+            None => return None,
+        };
+        Some(self.positions.iter().fold((first, first), |(min, max), p| (
+            Vector3::new(min.x().min(p.x()), min.y().min(p.y()), min.z().min(p.z())),
+            Vector3::new(max.x().max(p.x()), max.y().max(p.y()), max.z().max(p.z())))))
+    }
+
+    // not normalized; magnitude is twice the triangle's area
+    fn face_normal(&self, t : &[u32]) -> Vector3
+    {
+        let a = self.positions[t[0] as usize];
+        let b = self.positions[t[1] as usize];
+        let c = self.positions[t[2] as usize];
+        (b - a).cross(c - a)
+    }
+}
+
+pub(crate) fn unit_or_zero(v : Vector3) -> Vector3
+{
+    if v.magnitude_squared() > 0. { v.unit() } else { v }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::vector3::{ Vec3, Vector3 };
+    use ::matrices::AffineMatrix;
+    use ::mesh::Mesh;
+
+    // unit right triangle in the XY plane facing +Z
+    fn triangle() -> Mesh {
+        Mesh::new(vec![Vector3::zero(), Vector3::i_hat(), Vector3::j_hat()], vec![0, 1, 2])
+    }
+
+    // two triangles folded along the X axis
+    fn hinge() -> Mesh {
+        Mesh::new(
+            vec![Vector3::zero(), Vector3::i_hat(), Vector3::j_hat(), Vector3::k_hat()],
+            vec![0, 1, 2, 0, 3, 1])
+    }
+
+    #[test]
+    fn translation_moves_positions_only() {
+        let mesh = triangle().with_smooth_normals().apply(&AffineMatrix::translation(1., 2., 3.));
+        assert_eq!(mesh.positions()[0], Vector3::newi(1, 2, 3));
+        assert_eq!(mesh.normals().unwrap()[0], Vector3::k_hat());
+        assert_eq!(mesh.indices(), &[0, 1, 2]);
+    }
+
+    #[test]
+    fn normals_use_inverse_transpose() {
+        // a 45 degree slope stays perpendicular to its surface after stretching along X
+        let slope = Mesh::new(vec![Vector3::zero(), Vector3::j_hat(), Vector3::newi(1, 0, 1)], vec![0, 1, 2])
+            .with_smooth_normals();
+        let stretched = slope.apply(&AffineMatrix::scale(2., 1., 1.));
+
+        let normal = stretched.normals().unwrap()[0];
+        let edge = stretched.positions()[2] - stretched.positions()[0];
+        assert_approx_eq!(normal.dot(edge), 0.);
+        assert_approx_eq!(normal, stretched.with_smooth_normals().normals().unwrap()[0]);
+    }
+
+    #[test]
+    fn tangents_transform_as_directions() {
+        let mesh = triangle()
+            .with_tangents(vec![Vector3::i_hat(); 3])
+            .apply(&(AffineMatrix::translation(4., 0., 0.) * AffineMatrix::uniform_scale(3.)));
+        assert_eq!(mesh.tangents().unwrap()[1], Vector3::i_hat());
+    }
+
+    #[test]
+    fn reflection_flips_winding() {
+        let mirrored = triangle().with_smooth_normals().apply(&AffineMatrix::scale(1., 1., -1.));
+        assert_eq!(mirrored.indices(), &[0, 2, 1]);
+
+        // recomputed normals agree with the transformed ones
+        assert_approx_eq!(mirrored.normals().unwrap()[0], -Vector3::k_hat());
+        assert_approx_eq!(mirrored.with_smooth_normals().normals().unwrap()[0], -Vector3::k_hat());
+    }
+
+    #[test]
+    fn smooth_normals_average_faces() {
+        let mesh = hinge().with_smooth_normals();
+        let shared = mesh.normals().unwrap()[0];
+        assert_approx_eq!(shared, Vector3::newi(0, 1, 1).unit());
+        assert_eq!(mesh.positions().len(), 4);
+    }
+
+    #[test]
+    fn flat_normals_split_vertices() {
+        let mesh = hinge().with_flat_normals();
+        assert_eq!(mesh.positions().len(), 6);
+        assert_eq!(mesh.triangle_count(), 2);
+        assert_eq!(mesh.normals().unwrap()[0], Vector3::k_hat());
+        assert_eq!(mesh.normals().unwrap()[3], Vector3::j_hat());
+    }
+
+    #[test]
+    fn bounding_box_after_transform() {
+        let mesh = hinge().apply(&AffineMatrix::uniform_scale(2.));
+        let (min, max) = mesh.bounding_box().unwrap();
+        assert_eq!(min, Vector3::zero());
+        assert_eq!(max, Vector3::newi(2, 2, 2));
+        assert_eq!(Mesh::new(vec![], vec![]).bounding_box(), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_out_of_bounds() {
+        Mesh::new(vec![Vector3::zero()], vec![0, 1, 2]);
+    }
+}
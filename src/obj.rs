@@ -0,0 +1,479 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::{ BufRead, Write };
+use ::vector3::{ Vec3, Vector3 };
+use ::matrices::AffineMatrix;
+use ::matrix3::Matrix3;
+use ::mesh::{ Mesh, unit_or_zero };
+
+// Wavefront OBJ geometry. Texture coordinates are stored as (u, v, w) in a Vector3.
+// Face indices are zero based and already resolved from OBJ's one based/relative form.
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Obj
+{
+    pub positions : Vec<Vector3>,
+    pub texcoords : Vec<Vector3>,
+    pub normals : Vec<Vector3>,
+    pub groups : Vec<Group>,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum GroupKind
+{
+    Object,  // 'o'
+    Group,   // 'g'
+}
+
+// Faces listed under one 'o' or 'g' statement. Faces that appear before any
+// statement land in an unnamed group, which is written back without a 'g' line;
+// unnamed groups after it are written as a bare 'g'.
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Group
+{
+    pub kind : GroupKind,
+    pub name : String,
+    pub faces : Vec<Face>,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Face
+{
+    pub vertices : Vec<FaceVertex>,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct FaceVertex
+{
+    pub position : usize,
+    pub texcoord : Option<usize>,
+    pub normal : Option<usize>,
+}
+
+#[derive(Debug)]
+pub enum ObjError
+{
+    Io(io::Error),
+    Parse { line : usize, message : String },
+}
+
+impl fmt::Display for ObjError
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            ObjError::Io(e) => write!(f, "OBJ io error: {}", e),
+            ObjError::Parse { line, message } => write!(f, "OBJ parse error on line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for ObjError {}
+
+impl From<io::Error> for ObjError
+{
+    fn from(e : io::Error) -> ObjError { ObjError::Io(e) }
+}
+
+impl Obj
+{
+    pub fn new() -> Obj
+    {
+        Obj { positions: vec![], texcoords: vec![], normals: vec![], groups: vec![] }
+    }
+
+    pub fn parse(text : &str) -> Result<Obj, ObjError>
+    {
+        Obj::read(text.as_bytes())
+    }
+
+    pub fn parse_transformed(text : &str, m : &AffineMatrix) -> Result<Obj, ObjError>
+    {
+        Obj::parse(text).map(|obj| obj.apply(m))
+    }
+
+    // Unsupported statements (materials, smoothing groups, lines, ...) are skipped
+    pub fn read<R : BufRead>(reader : R) -> Result<Obj, ObjError>
+    {
+        let mut obj = Obj::new();
+
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let number = number + 1;
+            let error = |message : String| ObjError::Parse { line: number, message };
+
+            let content = line.split('#').next().unwrap_or("");
+            let mut tokens = content.split_whitespace();
+            let keyword = match tokens.next() {
+                Some(k) => k,
+                None => continue,
+            };
+            let args : Vec<&str> = tokens.collect();
+
+            match keyword {
+                "v" => obj.positions.push(parse_position(&args).map_err(error)?),
+                "vn" => obj.normals.push(parse_vector(&args, 3, 0.).map_err(error)?),
+                "vt" => obj.texcoords.push(parse_vector(&args, 1, 0.).map_err(error)?),
+                "f" => {
+                    if args.len() < 3 {
+                        return Err(error(format!("face needs at least 3 vertices, found {}", args.len())));
+                    }
+                    let vertices = args.iter()
+                        .map(|a| parse_face_vertex(a, &obj))
+                        .collect::<Result<Vec<FaceVertex>, String>>()
+                        .map_err(error)?;
+                    if obj.groups.is_empty() {
+                        obj.groups.push(Group { kind: GroupKind::Group, name: String::new(), faces: vec![] });
+                    }
+                    obj.groups.last_mut().unwrap().faces.push(Face { vertices });
+                },
+                "o" | "g" => {
+                    let kind = if keyword == "o" { GroupKind::Object } else { GroupKind::Group };
+                    obj.groups.push(Group { kind, name: args.join(" "), faces: vec![] });
+                },
+                _ => {},
+            }
+        }
+
+        Ok(obj)
+    }
+
+    pub fn write<W : Write>(&self, w : &mut W) -> io::Result<()>
+    {
+        for p in &self.positions {
+            writeln!(w, "v {} {} {}", p.x(), p.y(), p.z())?;
+        }
+        for t in &self.texcoords {
+            writeln!(w, "vt {} {} {}", t.x(), t.y(), t.z())?;
+        }
+        for n in &self.normals {
+            writeln!(w, "vn {} {} {}", n.x(), n.y(), n.z())?;
+        }
+        for (i, group) in self.groups.iter().enumerate() {
+            match (group.kind, group.name.is_empty()) {
+                (GroupKind::Object, _) => writeln!(w, "o {}", group.name)?,
+                (GroupKind::Group, false) => writeln!(w, "g {}", group.name)?,
+                // reading recreates the leading implicit group from its faces alone
+                (GroupKind::Group, true) if i == 0 && !group.faces.is_empty() => {},
+                (GroupKind::Group, true) => writeln!(w, "g")?,
+            }
+            for face in &group.faces {
+                write!(w, "f")?;
+                for v in &face.vertices {
+                    match (v.texcoord, v.normal) {
+                        (None, None) => write!(w, " {}", v.position + 1)?,
+                        (Some(t), None) => write!(w, " {}/{}", v.position + 1, t + 1)?,
+                        (None, Some(n)) => write!(w, " {}//{}", v.position + 1, n + 1)?,
+                        (Some(t), Some(n)) => write!(w, " {}/{}/{}", v.position + 1, t + 1, n + 1)?,
+                    }
+                }
+                writeln!(w)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn write_transformed<W : Write>(&self, w : &mut W, m : &AffineMatrix) -> io::Result<()>
+    {
+        self.apply(m).write(w)
+    }
+
+    pub fn to_obj_string(&self) -> String
+    {
+        let mut bytes = vec![];
+        self.write(&mut bytes).expect("writing to a Vec cannot fail");
+        String::from_utf8(bytes).expect("OBJ output is always utf8")
+    }
+
+    // Positions transform as points, normals by the inverse-transpose; mirroring
+    // transforms reverse face winding. Texture coordinates are left alone.
+    pub fn apply(&self, m : &AffineMatrix) -> Obj
+    {
+        let normal_matrix = Matrix3::normal_matrix(m);
+        let flip = m.determinant() < 0.;
+        Obj {
+            positions: self.positions.iter().map(|&p| m.apply_vec3(p)).collect(),
+            texcoords: self.texcoords.clone(),
+            normals: self.normals.iter().map(|&n| unit_or_zero(normal_matrix.apply_vec3(n))).collect(),
+            groups: self.groups.iter().map(|g| Group {
+                kind: g.kind,
+                name: g.name.clone(),
+                faces: g.faces.iter().map(|f| if flip { f.reversed() } else { f.clone() }).collect(),
+            }).collect(),
+        }
+    }
+
+    pub fn face_count(&self) -> usize
+    {
+        self.groups.iter().map(|g| g.faces.len()).sum()
+    }
+
+    // Fan-triangulates every face and merges the separate OBJ index streams into one
+    // vertex per distinct (position, normal) pair. Normals are kept only if every face has them.
+    // Faces with fewer than 3 vertices, which only code building an Obj by hand can produce,
+    // are skipped.
+    pub fn to_mesh(&self) -> Mesh
+    {
+        let faces : Vec<&Face> = self.groups.iter().flat_map(|g| g.faces.iter()).filter(|f| f.vertices.len() >= 3).collect();
+        let with_normals = !faces.is_empty() && faces.iter().all(|f| f.vertices.iter().all(|v| v.normal.is_some()));
+
+        let mut lookup = HashMap::new();
+        let mut positions = vec![];
+        let mut normals = vec![];
+        let mut indices = vec![];
+
+        for face in faces {
+            let corners : Vec<u32> = face.vertices.iter().map(|v| {
+                let key = (v.position, if with_normals { v.normal } else { None });
+                *lookup.entry(key).or_insert_with(|| {
+                    positions.push(self.positions[v.position]);
+                    if let Some(n) = key.1 {
+                        normals.push(self.normals[n]);
+                    }
+                    (positions.len() - 1) as u32
+                })
+            }).collect();
+            for i in 1..corners.len() - 1 {
+                indices.extend_from_slice(&[corners[0], corners[i], corners[i + 1]]);
+            }
+        }
+
+        let mesh = Mesh::new(positions, indices);
+        if with_normals { mesh.with_normals(normals) } else { mesh }
+    }
+
+    pub fn from_mesh(mesh : &Mesh) -> Obj
+    {
+        let has_normals = mesh.normals().is_some();
+        let faces = mesh.indices().chunks(3).map(|t| Face {
+            vertices: t.iter().map(|&i| FaceVertex {
+                position: i as usize,
+                texcoord: None,
+                normal: if has_normals { Some(i as usize) } else { None },
+            }).collect()
+        }).collect();
+
+        Obj {
+            positions: mesh.positions().to_vec(),
+            texcoords: vec![],
+            normals: mesh.normals().map(|n| n.to_vec()).unwrap_or_default(),
+            groups: vec![Group { kind: GroupKind::Group, name: String::new(), faces }],
+        }
+    }
+}
+
+impl Default for Obj
+{
+    fn default() -> Obj { Obj::new() }
+}
+
+impl Face
+{
+    // same polygon with the opposite winding, keeping the first vertex in place
+    pub fn reversed(&self) -> Face
+    {
+        let mut vertices = self.vertices.clone();
+        if let Some(rest) = vertices.get_mut(1..) {
+            rest.reverse();
+        }
+        Face { vertices }
+    }
+}
+
+// x y z, or x y z w which is divided through. Six or more values are taken to be the
+// common x y z r g b vertex color extension, and the colors are dropped.
+fn parse_position(args : &[&str]) -> Result<Vector3, String>
+{
+    let v = parse_vector(args, 3, 0.)?;
+    match args.len() {
+        4 => {},
+        5 => return Err(format!("expected 3, 4 or at least 6 values, found {}", args.len())),
+        _ => return Ok(v),
+    }
+    let w = args[3].parse::<f32>().map_err(|_| format!("invalid number '{}'", args[3]))?;
+    if w == 0. {
+        return Err("vertex weight w is zero".to_string());
+    }
+    Ok(v * (1. / w))
+}
+
+fn parse_vector(args : &[&str], required : usize, default : f32) -> Result<Vector3, String>
+{
+    if args.len() < required {
+        return Err(format!("expected at least {} coordinates, found {}", required, args.len()));
+    }
+    let mut values = [default; 3];
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = arg.parse::<f32>().map_err(|_| format!("invalid number '{}'", arg))?;
+    }
+    Ok(Vector3::new(values[0], values[1], values[2]))
+}
+
+fn parse_face_vertex(arg : &str, obj : &Obj) -> Result<FaceVertex, String>
+{
+    let mut parts = arg.split('/');
+    let position = parts.next().unwrap_or("");
+    let texcoord = parts.next().unwrap_or("");
+    let normal = parts.next().unwrap_or("");
+    if parts.next().is_some() {
+        return Err(format!("invalid face vertex '{}'", arg));
+    }
+
+    Ok(FaceVertex {
+        position: resolve_index(position, obj.positions.len(), "position")?,
+        texcoord: if texcoord.is_empty() { None } else { Some(resolve_index(texcoord, obj.texcoords.len(), "texture coordinate")?) },
+        normal: if normal.is_empty() { None } else { Some(resolve_index(normal, obj.normals.len(), "normal")?) },
+    })
+}
+
+// OBJ indices are one based, or relative to the end of the list when negative
+fn resolve_index(token : &str, count : usize, what : &str) -> Result<usize, String>
+{
+    let index = token.parse::<i64>().map_err(|_| format!("invalid {} index '{}'", what, token))?;
+    let resolved = if index > 0 { index - 1 } else { count as i64 + index };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("{} index {} out of range (have {})", what, index, count));
+    }
+    Ok(resolved as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use ::vector3::{ Vec3, Vector3 };
+    use ::matrices::AffineMatrix;
+    use ::obj::{ Obj, ObjError, GroupKind, Face, FaceVertex };
+
+    const QUAD : &str = "\
+# two groups sharing a quad's corners
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 1
+vn 0 0 1
+o panel
+f 1/1/1 2/2/1 3/1/1 4/2/1
+g back
+f 1//1 4//1 3//1
+f -4 -2 -3
+";
+
+    #[test]
+    fn parse_counts() {
+        let obj = Obj::parse(QUAD).unwrap();
+        assert_eq!(obj.positions.len(), 4);
+        assert_eq!(obj.texcoords[1], Vector3::newi(1, 1, 0));
+        assert_eq!(obj.normals, vec![Vector3::k_hat()]);
+        assert_eq!(obj.groups.len(), 2);
+        assert_eq!(obj.groups[0].kind, GroupKind::Object);
+        assert_eq!(obj.groups[1].name, "back");
+        assert_eq!(obj.face_count(), 3);
+    }
+
+    #[test]
+    fn face_vertex_forms() {
+        let obj = Obj::parse(QUAD).unwrap();
+        assert_eq!(obj.groups[0].faces[0].vertices[1], FaceVertex { position: 1, texcoord: Some(1), normal: Some(0) });
+        assert_eq!(obj.groups[1].faces[0].vertices[1], FaceVertex { position: 3, texcoord: None, normal: Some(0) });
+        // relative indices count back from the most recent vertex
+        assert_eq!(obj.groups[1].faces[1].vertices[0], FaceVertex { position: 0, texcoord: None, normal: None });
+    }
+
+    #[test]
+    fn round_trip() {
+        let obj = Obj::parse(QUAD).unwrap();
+        let again = Obj::parse(&obj.to_obj_string()).unwrap();
+        assert_eq!(obj, again);
+    }
+
+    #[test]
+    fn round_trip_unnamed_groups() {
+        let obj = Obj::parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\ng named\nf 1 2 3\ng\nf 3 2 1\nf 1 3 2\n").unwrap();
+        assert_eq!(obj.groups.len(), 3);
+        assert_eq!(obj.groups[2].name, "");
+        let text = obj.to_obj_string();
+        assert!(text.starts_with("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n"));
+        assert_eq!(Obj::parse(&text).unwrap(), obj);
+    }
+
+    #[test]
+    fn degenerate_faces_skipped() {
+        let mut obj = Obj::parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        let corner = obj.groups[0].faces[0].vertices[0];
+        obj.groups[0].faces.push(Face { vertices: vec![] });
+        obj.groups[0].faces.push(Face { vertices: vec![corner, corner] });
+        assert_eq!(obj.to_mesh().triangle_count(), 1);
+        assert_eq!(obj.apply(&AffineMatrix::scale(-1., 1., 1.)).face_count(), 3);
+    }
+
+    #[test]
+    fn homogeneous_positions() {
+        let obj = Obj::parse("v 2 4 6 2\nv 1 2 3 1 0.5 0.5\n").unwrap();
+        assert_eq!(obj.positions, vec![Vector3::newi(1, 2, 3), Vector3::newi(1, 2, 3)]);
+        assert!(Obj::parse("v 1 2 3 1 1\n").is_err());
+        match Obj::parse("v 1 2 3 0\n") {
+            Err(ObjError::Parse { line, message }) => {
+                assert_eq!(line, 1);
+                assert!(message.contains("w"));
+            },
+            other => panic!("expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_errors_report_line() {
+        match Obj::parse("v 0 0 0\nv 1 0 0\nf 1 2 3\n") {
+            Err(ObjError::Parse { line, .. }) => assert_eq!(line, 3),
+            other => panic!("expected parse error, got {:?}", other),
+        }
+        match Obj::parse("# header\n\nv 0 zero 0\n") {
+            Err(ObjError::Parse { line, message }) => {
+                assert_eq!(line, 3);
+                assert!(message.contains("zero"));
+            },
+            other => panic!("expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn transform_on_load() {
+        let obj = Obj::parse_transformed(QUAD, &AffineMatrix::translation(0., 0., 5.)).unwrap();
+        assert_eq!(obj.positions[2], Vector3::newi(1, 1, 5));
+        assert_eq!(obj.normals[0], Vector3::k_hat());
+    }
+
+    #[test]
+    fn mirrored_save_reverses_faces() {
+        let obj = Obj::parse("v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        let mut out = vec![];
+        obj.write_transformed(&mut out, &AffineMatrix::scale(-1., 1., 1.)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "v 0 0 0\nv -1 0 0\nv 0 1 0\nf 1 3 2\n");
+    }
+
+    #[test]
+    fn to_mesh_triangulates() {
+        let mesh = Obj::parse(QUAD).unwrap().to_mesh();
+        assert_eq!(mesh.triangle_count(), 4);
+        assert_eq!(mesh.positions().len(), 4);
+        // the last face has no normals, so none are kept
+        assert_eq!(mesh.normals(), None);
+
+        let back = Obj::from_mesh(&mesh.with_smooth_normals());
+        assert_eq!(back.face_count(), 4);
+        assert_eq!(back.to_mesh(), mesh.with_smooth_normals());
+    }
+}
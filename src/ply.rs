@@ -0,0 +1,568 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::{ Read, Write };
+use ::vector3::{ Vec3, Vector3 };
+use ::vector4::{ Vec4, Vector4 };
+use ::matrices::AffineMatrix;
+use ::matrix3::Matrix3;
+use ::mesh::unit_or_zero;
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum PlyFormat
+{
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+// Point cloud read from the PLY 'vertex' element. Colors are RGBA in 0..1; integer
+// color channels are divided by their type's maximum (255 for uchar) and alpha defaults to 1.
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct PointCloud
+{
+    pub positions : Vec<Vector3>,
+    pub normals : Option<Vec<Vector3>>,
+    pub colors : Option<Vec<Vector4>>,
+}
+
+#[derive(Debug)]
+pub enum PlyError
+{
+    Io(io::Error),
+    Header { line : usize, message : String },
+    UnsupportedType { element : String, property : String, type_name : String },
+    Data(String),
+}
+
+impl fmt::Display for PlyError
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            PlyError::Io(e) => write!(f, "PLY io error: {}", e),
+            PlyError::Header { line, message } => write!(f, "PLY header error on line {}: {}", line, message),
+            PlyError::UnsupportedType { element, property, type_name } =>
+                write!(f, "PLY property '{}' of element '{}' has unsupported type '{}'", property, element, type_name),
+            PlyError::Data(message) => write!(f, "PLY data error: {}", message),
+        }
+    }
+}
+
+impl Error for PlyError {}
+
+impl From<io::Error> for PlyError
+{
+    fn from(e : io::Error) -> PlyError { PlyError::Io(e) }
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+enum Scalar
+{
+    I8, U8, I16, U16, I32, U32, F32, F64,
+}
+
+impl Scalar
+{
+    fn from_name(name : &str) -> Option<Scalar>
+    {
+        match name {
+            "char" | "int8" => Some(Scalar::I8),
+            "uchar" | "uint8" => Some(Scalar::U8),
+            "short" | "int16" => Some(Scalar::I16),
+            "ushort" | "uint16" => Some(Scalar::U16),
+            "int" | "int32" => Some(Scalar::I32),
+            "uint" | "uint32" => Some(Scalar::U32),
+            "float" | "float32" => Some(Scalar::F32),
+            "double" | "float64" => Some(Scalar::F64),
+            _ => None,
+        }
+    }
+
+    fn size(&self) -> usize
+    {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::I32 | Scalar::U32 | Scalar::F32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    // the largest value of an integer type, None for floats
+    fn max_value(&self) -> Option<f64>
+    {
+        match self {
+            Scalar::I8 => Some(i8::MAX as f64),
+            Scalar::U8 => Some(u8::MAX as f64),
+            Scalar::I16 => Some(i16::MAX as f64),
+            Scalar::U16 => Some(u16::MAX as f64),
+            Scalar::I32 => Some(i32::MAX as f64),
+            Scalar::U32 => Some(u32::MAX as f64),
+            Scalar::F32 | Scalar::F64 => None,
+        }
+    }
+}
+
+enum Property
+{
+    Scalar(String, Scalar),
+    List(Scalar, Scalar),  // count type, item type
+}
+
+struct Element
+{
+    name : String,
+    count : usize,
+    properties : Vec<Property>,
+}
+
+// Reads whitespace separated values (ascii) or fixed width values (binary) from the body
+struct Body<'a>
+{
+    format : PlyFormat,
+    bytes : &'a [u8],
+    offset : usize,
+}
+
+impl<'a> Body<'a>
+{
+    fn read(&mut self, kind : Scalar) -> Result<f64, PlyError>
+    {
+        match self.format {
+            PlyFormat::Ascii => {
+                let rest = &self.bytes[self.offset..];
+                let start = rest.iter().position(|b| !b.is_ascii_whitespace())
+                    .ok_or_else(|| PlyError::Data("unexpected end of data".to_string()))?;
+                let length = rest[start..].iter().position(|b| b.is_ascii_whitespace()).unwrap_or(rest.len() - start);
+                let token = String::from_utf8_lossy(&rest[start..start + length]).to_string();
+                self.offset += start + length;
+                token.parse::<f64>().map_err(|_| PlyError::Data(format!("invalid number '{}'", token)))
+            },
+            _ => {
+                let size = kind.size();
+                if self.offset + size > self.bytes.len() {
+                    return Err(PlyError::Data("unexpected end of data".to_string()));
+                }
+                let mut raw = [0u8; 8];
+                raw[..size].copy_from_slice(&self.bytes[self.offset..self.offset + size]);
+                if self.format == PlyFormat::BinaryBigEndian {
+                    raw[..size].reverse();
+                }
+                self.offset += size;
+                Ok(match kind {
+                    Scalar::I8 => raw[0] as i8 as f64,
+                    Scalar::U8 => raw[0] as f64,
+                    Scalar::I16 => i16::from_le_bytes([raw[0], raw[1]]) as f64,
+                    Scalar::U16 => u16::from_le_bytes([raw[0], raw[1]]) as f64,
+                    Scalar::I32 => i32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    Scalar::U32 => u32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    Scalar::F32 => f32::from_le_bytes([raw[0], raw[1], raw[2], raw[3]]) as f64,
+                    Scalar::F64 => f64::from_le_bytes(raw),
+                })
+            },
+        }
+    }
+}
+
+impl PointCloud
+{
+    pub fn new(positions : Vec<Vector3>) -> PointCloud
+    {
+        PointCloud { positions, normals: None, colors: None }
+    }
+
+    pub fn read<R : Read>(mut reader : R) -> Result<PointCloud, PlyError>
+    {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        PointCloud::from_bytes(&bytes)
+    }
+
+    pub fn read_transformed<R : Read>(reader : R, m : &AffineMatrix) -> Result<PointCloud, PlyError>
+    {
+        PointCloud::read(reader).map(|cloud| cloud.apply(m))
+    }
+
+    pub fn from_bytes(bytes : &[u8]) -> Result<PointCloud, PlyError>
+    {
+        let (format, elements, body_start) = parse_header(bytes)?;
+        let mut body = Body { format, bytes, offset: body_start };
+        let mut cloud = None;
+
+        for element in &elements {
+            if element.name != "vertex" {
+                for _ in 0..element.count {
+                    for property in &element.properties {
+                        skip_property(&mut body, property)?;
+                    }
+                }
+                continue;
+            }
+            cloud = Some(read_vertices(&mut body, element)?);
+        }
+
+        cloud.ok_or_else(|| PlyError::Header { line: 0, message: "no 'vertex' element".to_string() })
+    }
+
+    // Fails with InvalidInput, before writing anything, if the normals or colors do not
+    // have one entry per position.
+    pub fn write<W : Write>(&self, w : &mut W, format : PlyFormat) -> io::Result<()>
+    {
+        let lengths = [("normals", self.normals.as_ref().map(|n| n.len())), ("colors", self.colors.as_ref().map(|c| c.len()))];
+        for &(name, length) in lengths.iter() {
+            match length {
+                Some(length) if length != self.positions.len() => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                    format!("{} {} for {} positions", length, name, self.positions.len()))),
+                _ => {},
+            }
+        }
+
+        writeln!(w, "ply")?;
+        writeln!(w, "format {} 1.0", match format {
+            PlyFormat::Ascii => "ascii",
+            PlyFormat::BinaryLittleEndian => "binary_little_endian",
+            PlyFormat::BinaryBigEndian => "binary_big_endian",
+        })?;
+        writeln!(w, "element vertex {}", self.positions.len())?;
+        writeln!(w, "property float x\nproperty float y\nproperty float z")?;
+        if self.normals.is_some() {
+            writeln!(w, "property float nx\nproperty float ny\nproperty float nz")?;
+        }
+        if self.colors.is_some() {
+            writeln!(w, "property uchar red\nproperty uchar green\nproperty uchar blue\nproperty uchar alpha")?;
+        }
+        writeln!(w, "end_header")?;
+
+        for (i, p) in self.positions.iter().enumerate() {
+            let mut floats = vec![p.x(), p.y(), p.z()];
+            if let Some(ref normals) = self.normals {
+                floats.extend_from_slice(&[normals[i].x(), normals[i].y(), normals[i].z()]);
+            }
+            let channels : Vec<u8> = match self.colors {
+                Some(ref colors) => [colors[i].x(), colors[i].y(), colors[i].z(), colors[i].w()].iter()
+                    .map(|c| (c.clamp(0., 1.) * 255.).round() as u8)
+                    .collect(),
+                None => vec![],
+            };
+
+            match format {
+                PlyFormat::Ascii => {
+                    let fields : Vec<String> = floats.iter().map(|f| f.to_string())
+                        .chain(channels.iter().map(|c| c.to_string()))
+                        .collect();
+                    writeln!(w, "{}", fields.join(" "))?;
+                },
+                PlyFormat::BinaryLittleEndian => {
+                    for f in &floats { w.write_all(&f.to_le_bytes())?; }
+                    w.write_all(&channels)?;
+                },
+                PlyFormat::BinaryBigEndian => {
+                    for f in &floats { w.write_all(&f.to_be_bytes())?; }
+                    w.write_all(&channels)?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    // Positions transform as points and normals by the inverse-transpose; colors are kept
+    pub fn apply(&self, m : &AffineMatrix) -> PointCloud
+    {
+        let normal_matrix = Matrix3::normal_matrix(m);
+        PointCloud {
+            positions: self.positions.iter().map(|&p| m.apply_vec3(p)).collect(),
+            normals: self.normals.as_ref()
+                .map(|n| n.iter().map(|&v| unit_or_zero(normal_matrix.apply_vec3(v))).collect()),
+            colors: self.colors.clone(),
+        }
+    }
+}
+
+fn parse_header(bytes : &[u8]) -> Result<(PlyFormat, Vec<Element>, usize), PlyError>
+{
+    let mut format = None;
+    let mut elements : Vec<Element> = vec![];
+    let mut offset = 0;
+    let mut number = 0;
+
+    loop {
+        number += 1;
+        let error = |message : String| PlyError::Header { line: number, message };
+        let end = match bytes[offset..].iter().position(|&b| b == b'\n') {
+            Some(end) => offset + end,
+            None => return Err(error("missing 'end_header'".to_string())),
+        };
+        let line = String::from_utf8_lossy(&bytes[offset..end]).to_string();
+        offset = end + 1;
+        let tokens : Vec<&str> = line.split_whitespace().collect();
+
+        if number == 1 {
+            if tokens != ["ply"] {
+                return Err(error("file does not start with 'ply'".to_string()));
+            }
+            continue;
+        }
+
+        match tokens.first().cloned() {
+            None | Some("comment") | Some("obj_info") => {},
+            Some("format") => {
+                format = Some(match tokens.get(1).cloned() {
+                    Some("ascii") => PlyFormat::Ascii,
+                    Some("binary_little_endian") => PlyFormat::BinaryLittleEndian,
+                    Some("binary_big_endian") => PlyFormat::BinaryBigEndian,
+                    _ => return Err(error(format!("unknown format '{}'", tokens[1..].join(" ")))),
+                });
+            },
+            Some("element") => {
+                if tokens.len() != 3 {
+                    return Err(error("expected 'element <name> <count>'".to_string()));
+                }
+                let count = tokens[2].parse::<usize>().map_err(|_| error(format!("invalid element count '{}'", tokens[2])))?;
+                elements.push(Element { name: tokens[1].to_string(), count, properties: vec![] });
+            },
+            Some("property") => {
+                let element = match elements.last_mut() {
+                    Some(element) => element,
+                    None => return Err(error("property before any element".to_string())),
+                };
+                let unsupported = |property : &str, type_name : &str| PlyError::UnsupportedType {
+                    element: element.name.clone(), property: property.to_string(), type_name: type_name.to_string()
+                };
+                let property = match tokens.len() {
+                    3 => Property::Scalar(tokens[2].to_string(),
+                        Scalar::from_name(tokens[1]).ok_or_else(|| unsupported(tokens[2], tokens[1]))?),
+                    5 if tokens[1] == "list" => Property::List(
+                        Scalar::from_name(tokens[2]).ok_or_else(|| unsupported(tokens[4], tokens[2]))?,
+                        Scalar::from_name(tokens[3]).ok_or_else(|| unsupported(tokens[4], tokens[3]))?),
+                    _ => return Err(error(format!("malformed property '{}'", line.trim()))),
+                };
+                element.properties.push(property);
+            },
+            Some("end_header") => break,
+            Some(other) => return Err(error(format!("unexpected '{}'", other))),
+        }
+    }
+
+    match format {
+        Some(format) => Ok((format, elements, offset)),
+        None => Err(PlyError::Header { line: number, message: "missing 'format'".to_string() }),
+    }
+}
+
+fn skip_property(body : &mut Body, property : &Property) -> Result<(), PlyError>
+{
+    match *property {
+        Property::Scalar(_, kind) => { body.read(kind)?; },
+        Property::List(count_kind, item_kind) => {
+            let count = body.read(count_kind)? as usize;
+            for _ in 0..count {
+                body.read(item_kind)?;
+            }
+        },
+    }
+    Ok(())
+}
+
+fn read_vertices(body : &mut Body, element : &Element) -> Result<PointCloud, PlyError>
+{
+    let find = |name : &str| element.properties.iter().position(|p| match p {
+        Property::Scalar(n, _) => n == name,
+        Property::List(..) => false,
+    });
+    let slots = |names : &[&str]| -> Option<Vec<usize>> { names.iter().map(|n| find(n)).collect() };
+
+    let position_slots = slots(&["x", "y", "z"])
+        .ok_or_else(|| PlyError::Data("vertex element needs scalar x, y and z properties".to_string()))?;
+    let normal_slots = slots(&["nx", "ny", "nz"]);
+    let color_slots = slots(&["red", "green", "blue"]);
+    let alpha_slot = find("alpha");
+
+    // the header's count is untrusted; every vertex takes at least a byte per property
+    let remaining = body.bytes.len() - body.offset;
+    let mut positions = Vec::with_capacity(element.count.min(remaining / element.properties.len().max(1)));
+    let mut normals = vec![];
+    let mut colors = vec![];
+    let mut values = vec![0.; element.properties.len()];
+
+    for _ in 0..element.count {
+        for (i, property) in element.properties.iter().enumerate() {
+            match *property {
+                Property::Scalar(_, kind) => {
+                    let value = body.read(kind)?;
+                    let is_channel = Some(i) == alpha_slot || is_color(&color_slots, i);
+                    values[i] = match kind.max_value() {
+                        Some(max) if is_channel => value / max,
+                        _ => value,
+                    };
+                },
+                Property::List(..) => skip_property(body, property)?,
+            }
+        }
+        let vector = |s : &[usize]| Vector3::new(values[s[0]] as f32, values[s[1]] as f32, values[s[2]] as f32);
+        positions.push(vector(&position_slots));
+        if let Some(ref s) = normal_slots {
+            normals.push(vector(s));
+        }
+        if let Some(ref s) = color_slots {
+            let alpha = alpha_slot.map(|a| values[a] as f32).unwrap_or(1.);
+            colors.push(Vector4::new(values[s[0]] as f32, values[s[1]] as f32, values[s[2]] as f32, alpha));
+        }
+    }
+
+    Ok(PointCloud {
+        positions,
+        normals: normal_slots.map(|_| normals),
+        colors: color_slots.map(|_| colors),
+    })
+}
+
+fn is_color(slots : &Option<Vec<usize>>, i : usize) -> bool
+{
+    slots.as_ref().map(|s| s.contains(&i)).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use ::vector3::{ Vec3, Vector3 };
+    use ::vector4::Vector4;
+    use ::matrices::AffineMatrix;
+    use ::ply::{ PointCloud, PlyError, PlyFormat };
+    use std::io;
+
+    const SCAN : &str = "\
+ply
+format ascii 1.0
+comment two points and a face that should be skipped
+element vertex 2
+property float x
+property float y
+property float z
+property float nx
+property float ny
+property float nz
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+0 0 0 0 0 1 255 0 0
+1 2 3 1 0 0 0 51 255
+3 0 1 1
+";
+
+    fn cloud() -> PointCloud {
+        PointCloud {
+            positions: vec![Vector3::zero(), Vector3::new(1.5, -2., 3.25)],
+            normals: Some(vec![Vector3::k_hat(), Vector3::i_hat()]),
+            colors: Some(vec![Vector4::new(1., 0., 0., 1.), Vector4::new(0., 0.2, 1., 0.6)]),
+        }
+    }
+
+    #[test]
+    fn parse_ascii() {
+        let cloud = PointCloud::from_bytes(SCAN.as_bytes()).unwrap();
+        assert_eq!(cloud.positions, vec![Vector3::zero(), Vector3::newi(1, 2, 3)]);
+        assert_eq!(cloud.normals.unwrap()[1], Vector3::i_hat());
+        assert_eq!(cloud.colors.unwrap()[1], Vector4::new(0., 0.2, 1., 1.));
+    }
+
+    #[test]
+    fn wide_color_channels() {
+        let text = "ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nproperty float z\n\
+                    property ushort red\nproperty ushort green\nproperty ushort blue\nproperty float alpha\nend_header\n\
+                    0 0 0 65535 0 13107 0.5\n";
+        let cloud = PointCloud::from_bytes(text.as_bytes()).unwrap();
+        assert_eq!(cloud.colors.unwrap()[0], Vector4::new(1., 0., 0.2, 0.5));
+    }
+
+    #[test]
+    fn positions_only() {
+        let text = "ply\nformat ascii 1.0\nelement vertex 1\nproperty double x\nproperty double y\nproperty double z\nend_header\n4 5 6\n";
+        let cloud = PointCloud::from_bytes(text.as_bytes()).unwrap();
+        assert_eq!(cloud, PointCloud::new(vec![Vector3::newi(4, 5, 6)]));
+    }
+
+    #[test]
+    fn round_trip_every_format() {
+        for &format in [PlyFormat::Ascii, PlyFormat::BinaryLittleEndian, PlyFormat::BinaryBigEndian].iter() {
+            let mut out = vec![];
+            cloud().write(&mut out, format).unwrap();
+            assert_eq!(PointCloud::read(&out[..]).unwrap(), cloud());
+        }
+    }
+
+    #[test]
+    fn mismatched_lengths_rejected() {
+        let mut short = cloud();
+        short.normals.as_mut().unwrap().pop();
+        let mut long = cloud();
+        long.colors.as_mut().unwrap().push(Vector4::newi(1, 1, 1, 1));
+
+        for &(ref bad, message) in [(short, "normals"), (long, "colors")].iter() {
+            let mut out = vec![];
+            let error = bad.write(&mut out, PlyFormat::Ascii).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+            assert!(error.to_string().contains(message));
+            assert!(out.is_empty());
+        }
+    }
+
+    #[test]
+    fn big_endian_layout() {
+        let mut out = vec![];
+        PointCloud::new(vec![Vector3::i_hat()]).write(&mut out, PlyFormat::BinaryBigEndian).unwrap();
+        assert_eq!(&out[out.len() - 12..out.len() - 8], &1f32.to_be_bytes());
+    }
+
+    #[test]
+    fn unsupported_property_type() {
+        let text = "ply\nformat ascii 1.0\nelement vertex 1\nproperty int64 x\nend_header\n";
+        match PointCloud::from_bytes(text.as_bytes()) {
+            Err(PlyError::UnsupportedType { property, type_name, .. }) => {
+                assert_eq!(property, "x");
+                assert_eq!(type_name, "int64");
+            },
+            other => panic!("expected unsupported type, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_binary_body() {
+        let mut out = vec![];
+        cloud().write(&mut out, PlyFormat::BinaryLittleEndian).unwrap();
+        out.truncate(out.len() - 3);
+        match PointCloud::read(&out[..]) {
+            Err(PlyError::Data(_)) => {},
+            other => panic!("expected data error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn huge_vertex_count_fails_cleanly() {
+        let text = "ply\nformat binary_little_endian 1.0\nelement vertex 4000000000000\nproperty float x\nproperty float y\nproperty float z\nend_header\n";
+        let mut bytes = text.as_bytes().to_vec();
+        bytes.extend_from_slice(&[0; 12]);
+        match PointCloud::read(&bytes[..]) {
+            Err(PlyError::Data(_)) => {},
+            other => panic!("expected data error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn transform_whole_cloud() {
+        let moved = cloud().apply(&(AffineMatrix::uniform_scale(2.) * AffineMatrix::translation(1., 0., 0.)));
+        assert_eq!(moved.positions[1], Vector3::new(4., -4., 6.5));
+        assert_eq!(moved.normals.unwrap()[0], Vector3::k_hat());
+        assert_eq!(moved.colors, cloud().colors);
+    }
+}
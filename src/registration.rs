@@ -0,0 +1,187 @@
+use std::error::Error;
+use std::fmt;
+use ::vector3::{ Vec3, Vector3 };
+use ::matrix3::Matrix3;
+use ::matrices::AffineMatrix;
+
+// Best-fit rigid (or similarity) transforms between corresponding point sets, by the
+// Kabsch / Umeyama method: center both sets, take the SVD of their cross-covariance, and
+// read the rotation off its singular vectors.
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Default)]
+pub struct AlignOptions
+{
+    // also fit a uniform scale
+    pub scale : bool,
+    // allow a mirrored result when it fits better, rather than always a proper rotation
+    pub allow_reflection : bool,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Alignment
+{
+    // takes source points onto their targets
+    pub transform : AffineMatrix,
+    pub scale : f32,
+    // root mean square distance between transformed sources and targets, weighted
+    pub rms : f32,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum RegistrationError
+{
+    LengthMismatch { source : usize, target : usize },
+    WeightsMismatch { points : usize, weights : usize },
+    NegativeWeight { index : usize },
+    // no points, or all of their weights are zero
+    Empty,
+}
+
+impl fmt::Display for RegistrationError
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            RegistrationError::LengthMismatch { source, target } => write!(f, "{} source points but {} targets", source, target),
+            RegistrationError::WeightsMismatch { points, weights } => write!(f, "{} points but {} weights", points, weights),
+            RegistrationError::NegativeWeight { index } => write!(f, "weight {} is negative", index),
+            RegistrationError::Empty => write!(f, "no weighted points to align"),
+        }
+    }
+}
+
+impl Error for RegistrationError {}
+
+pub fn align(source : &[Vector3], target : &[Vector3], options : AlignOptions) -> Result<Alignment, RegistrationError>
+{
+    align_weighted(source, target, &vec![1.; source.len()], options)
+}
+
+// At least three non-collinear points with non-zero weight are needed for a unique answer;
+// with fewer the rotation about the line through them is arbitrary.
+pub fn align_weighted(source : &[Vector3], target : &[Vector3], weights : &[f32], options : AlignOptions) -> Result<Alignment, RegistrationError>
+{
+    if source.len() != target.len() {
+        return Err(RegistrationError::LengthMismatch { source: source.len(), target: target.len() });
+    }
+    if weights.len() != source.len() {
+        return Err(RegistrationError::WeightsMismatch { points: source.len(), weights: weights.len() });
+    }
+    if let Some(index) = weights.iter().position(|&w| w < 0.) {
+        return Err(RegistrationError::NegativeWeight { index });
+    }
+    let total : f32 = weights.iter().sum();
+    if total <= 0. {
+        return Err(RegistrationError::Empty);
+    }
+
+    let centroid = |points : &[Vector3]| points.iter().zip(weights).fold(Vector3::zero(), |sum, (&p, &w)| sum + p * w) * (1. / total);
+    let (source_center, target_center) = (centroid(source), centroid(target));
+
+    let mut covariance = Matrix3::zero();
+    let mut source_variance = 0.;
+    for ((&s, &t), &w) in source.iter().zip(target).zip(weights) {
+        let (s, t) = (s - source_center, t - target_center);
+        covariance = covariance + Matrix3::outer(t, s) * w;
+        source_variance += s.magnitude_squared() * w;
+    }
+
+    // R = U D Vᵀ, where D flips the weakest axis if U Vᵀ would otherwise be a reflection
+    let svd = (covariance * (1. / total)).svd();
+    let flip = !options.allow_reflection && svd.u.determinant() * svd.v.determinant() < 0.;
+    let d = if flip { -1. } else { 1. };
+    let rotation = svd.v.transpose() * Matrix3::scale(1., 1., d) * svd.u;
+
+    let sigma = svd.singular_values;
+    let scale = if options.scale && source_variance > 0. {
+        (sigma.x() + sigma.y() + sigma.z() * d) / (source_variance / total)
+    } else {
+        1.
+    };
+
+    let linear = rotation * scale;
+    let translation = target_center - linear * source_center;
+    let transform = AffineMatrix::from_basis(linear.column(1), linear.column(2), linear.column(3), translation);
+
+    let squared_error : f32 = source.iter().zip(target).zip(weights)
+        .map(|((&s, &t), &w)| (transform.apply_vec3(s) - t).magnitude_squared() * w)
+        .sum();
+
+    Ok(Alignment { transform, scale, rms: (squared_error / total).sqrt() })
+}
+
+#[cfg(test)]
+mod tests {
+    use ::registration::{ AlignOptions, RegistrationError, align, align_weighted };
+    use ::matrices::AffineMatrix;
+    use ::vector3::{ Vec3, Vector3 };
+
+    fn fixture() -> Vec<Vector3> {
+        vec![Vector3::newi(0, 0, 0), Vector3::newi(4, 0, 0), Vector3::newi(0, 3, 0), Vector3::newi(0, 0, 2), Vector3::newi(1, 2, 3)]
+    }
+
+    #[test]
+    fn recovers_rigid_transform() {
+        let truth = AffineMatrix::rotation_axis(Vector3::newi(1, 2, -1), 0.9) * AffineMatrix::translation(5., -3., 2.);
+        let measured : Vec<Vector3> = fixture().iter().map(|&p| truth.apply_vec3(p)).collect();
+
+        let fit = align(&fixture(), &measured, AlignOptions::default()).unwrap();
+        assert_approx_eq!(fit.transform, truth, epsilon = 0.0001);
+        assert_approx_eq!(fit.rms, 0., epsilon = 0.0001);
+        assert_eq!(fit.scale, 1.);
+    }
+
+    #[test]
+    fn recovers_uniform_scale() {
+        let truth = AffineMatrix::uniform_scale(2.5) * AffineMatrix::rotation_z(-0.4) * AffineMatrix::translation(1., 1., 1.);
+        let measured : Vec<Vector3> = fixture().iter().map(|&p| truth.apply_vec3(p)).collect();
+
+        let fit = align(&fixture(), &measured, AlignOptions { scale: true, ..AlignOptions::default() }).unwrap();
+        assert_approx_eq!(fit.scale, 2.5, epsilon = 0.0001);
+        assert_approx_eq!(fit.transform, truth, epsilon = 0.0001);
+
+        let rigid = align(&fixture(), &measured, AlignOptions::default()).unwrap();
+        assert!(rigid.rms > 1.);
+    }
+
+    #[test]
+    fn reflections() {
+        let mirrored : Vec<Vector3> = fixture().iter().map(|p| p.with_x(-p.x())).collect();
+
+        let rotation_only = align(&fixture(), &mirrored, AlignOptions::default()).unwrap();
+        assert_approx_eq!(rotation_only.transform.determinant(), 1., epsilon = 0.0001);
+        assert!(rotation_only.rms > 0.1);
+
+        let options = AlignOptions { allow_reflection: true, ..AlignOptions::default() };
+        let reflected = align(&fixture(), &mirrored, options).unwrap();
+        assert_approx_eq!(reflected.transform, AffineMatrix::scale(-1., 1., 1.), epsilon = 0.0001);
+        assert_approx_eq!(reflected.rms, 0., epsilon = 0.0001);
+    }
+
+    #[test]
+    fn weights_ignore_outliers() {
+        let truth = AffineMatrix::rotation_x(0.3) * AffineMatrix::translation(0., 2., 0.);
+        let mut measured : Vec<Vector3> = fixture().iter().map(|&p| truth.apply_vec3(p)).collect();
+        measured[4] = measured[4] + Vector3::newi(10, 0, 0);
+
+        let fit = align_weighted(&fixture(), &measured, &[1., 1., 1., 1., 0.], AlignOptions::default()).unwrap();
+        assert_approx_eq!(fit.transform, truth, epsilon = 0.0001);
+        assert!(align(&fixture(), &measured, AlignOptions::default()).unwrap().rms > 1.);
+    }
+
+    #[test]
+    fn errors() {
+        let options = AlignOptions::default();
+        assert_eq!(align(&fixture(), &fixture()[1..], options).unwrap_err(), RegistrationError::LengthMismatch { source: 5, target: 4 });
+        assert_eq!(align_weighted(&fixture(), &fixture(), &[1.], options).unwrap_err(), RegistrationError::WeightsMismatch { points: 5, weights: 1 });
+        assert_eq!(align_weighted(&fixture(), &fixture(), &[1., -1., 1., 1., 1.], options).unwrap_err(), RegistrationError::NegativeWeight { index: 1 });
+        assert_eq!(align(&[], &[], options).unwrap_err(), RegistrationError::Empty);
+    }
+}
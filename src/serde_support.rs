@@ -0,0 +1,388 @@
+use std::fmt;
+use serde::ser::{ Serialize, Serializer, SerializeStruct, SerializeTuple };
+use serde::de::{ Deserialize, Deserializer, Visitor, SeqAccess, MapAccess, Error };
+use ::vector3::{ Vec3, Vector3 };
+use ::vector4::{ Vec4, Vector4 };
+use ::matrices::{ AffineMatrix, CELL_NAMES };
+
+// Serde support, enabled with the `serde` feature.
+//
+// By default vectors are written as compact arrays, `[x, y, z]`, and matrices as
+// `{ "order": "row_major", "values": [16 numbers] }`. Wrap a value in `Named` to write
+// vectors as `{ "x": .., "y": .., "z": .. }` and matrices by cell name (`i1` .. `w4`), or
+// wrap a matrix in `ColumnMajor` to write its values in column-major order. Reading
+// accepts every form in human readable formats; binary formats read back what the
+// same wrapper wrote.
+
+const VECTOR3_FIELDS : &[&str] = &["x", "y", "z"];
+const VECTOR4_FIELDS : &[&str] = &["x", "y", "z", "w"];
+const MATRIX_FIELDS : &[&str] = &["order", "values"];
+const CELL_FIELDS : &[&str] = &CELL_NAMES;
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Named<T>(pub T);
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct ColumnMajor(pub AffineMatrix);
+
+fn serialize_values<S : Serializer>(serializer : S, values : &[f32]) -> Result<S::Ok, S::Error>
+{
+    let mut tuple = serializer.serialize_tuple(values.len())?;
+    for v in values {
+        tuple.serialize_element(v)?;
+    }
+    tuple.end()
+}
+
+fn serialize_fields<S : Serializer>(serializer : S, name : &'static str, fields : &[&'static str], values : &[f32]) -> Result<S::Ok, S::Error>
+{
+    let mut s = serializer.serialize_struct(name, fields.len())?;
+    for (field, v) in fields.iter().zip(values) {
+        s.serialize_field(field, v)?;
+    }
+    s.end()
+}
+
+fn serialize_matrix<S : Serializer>(serializer : S, order : &'static str, values : Vec<f32>) -> Result<S::Ok, S::Error>
+{
+    // a fixed size array keeps binary formats from writing a length
+    let mut array = [0f32; 16];
+    array.copy_from_slice(&values);
+    let mut s = serializer.serialize_struct("AffineMatrix", 2)?;
+    s.serialize_field("order", order)?;
+    s.serialize_field("values", &array)?;
+    s.end()
+}
+
+impl Serialize for Vector3
+{
+    fn serialize<S : Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error>
+    {
+        serialize_values(serializer, &[self.x(), self.y(), self.z()])
+    }
+}
+
+impl Serialize for Vector4
+{
+    fn serialize<S : Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error>
+    {
+        serialize_values(serializer, &[self.x(), self.y(), self.z(), self.w()])
+    }
+}
+
+impl Serialize for AffineMatrix
+{
+    fn serialize<S : Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error>
+    {
+        serialize_matrix(serializer, "row_major", self.as_row_major_vec())
+    }
+}
+
+impl Serialize for ColumnMajor
+{
+    fn serialize<S : Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error>
+    {
+        serialize_matrix(serializer, "column_major", self.0.as_column_major_vec())
+    }
+}
+
+impl Serialize for Named<Vector3>
+{
+    fn serialize<S : Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error>
+    {
+        serialize_fields(serializer, "Vector3", VECTOR3_FIELDS, &[self.0.x(), self.0.y(), self.0.z()])
+    }
+}
+
+impl Serialize for Named<Vector4>
+{
+    fn serialize<S : Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error>
+    {
+        serialize_fields(serializer, "Vector4", VECTOR4_FIELDS, &[self.0.x(), self.0.y(), self.0.z(), self.0.w()])
+    }
+}
+
+impl Serialize for Named<AffineMatrix>
+{
+    fn serialize<S : Serializer>(&self, serializer : S) -> Result<S::Ok, S::Error>
+    {
+        serialize_fields(serializer, "AffineMatrix", CELL_FIELDS, &self.0.as_row_major_vec())
+    }
+}
+
+// Reads a fixed number of floats from either an array or a map keyed by `fields`
+struct FloatsVisitor
+{
+    expecting : &'static str,
+    fields : &'static [&'static str],
+}
+
+impl<'de> Visitor<'de> for FloatsVisitor
+{
+    type Value = Vec<f32>;
+
+    fn expecting(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        f.write_str(self.expecting)
+    }
+
+    fn visit_seq<A : SeqAccess<'de>>(self, mut seq : A) -> Result<Vec<f32>, A::Error>
+    {
+        let mut values = Vec::with_capacity(self.fields.len());
+        for i in 0..self.fields.len() {
+            values.push(seq.next_element()?.ok_or_else(|| A::Error::invalid_length(i, &self))?);
+        }
+        if seq.next_element::<f32>()?.is_some() {
+            return Err(A::Error::invalid_length(self.fields.len() + 1, &self));
+        }
+        Ok(values)
+    }
+
+    fn visit_map<A : MapAccess<'de>>(self, mut map : A) -> Result<Vec<f32>, A::Error>
+    {
+        let mut values : Vec<Option<f32>> = vec![None; self.fields.len()];
+        while let Some(key) = map.next_key::<String>()? {
+            let index = self.fields.iter().position(|f| *f == key)
+                .ok_or_else(|| A::Error::unknown_field(&key, self.fields))?;
+            if values[index].is_some() {
+                return Err(A::Error::duplicate_field(self.fields[index]));
+            }
+            values[index] = Some(map.next_value()?);
+        }
+        values.iter().zip(self.fields)
+            .map(|(v, f)| v.ok_or_else(|| A::Error::missing_field(f)))
+            .collect()
+    }
+}
+
+fn deserialize_floats<'de, D : Deserializer<'de>>(deserializer : D, name : &'static str, expecting : &'static str, fields : &'static [&'static str], named : bool)
+    -> Result<Vec<f32>, D::Error>
+{
+    let visitor = FloatsVisitor { expecting, fields };
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(visitor)
+    } else if named {
+        deserializer.deserialize_struct(name, fields, visitor)
+    } else {
+        deserializer.deserialize_tuple(fields.len(), visitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Vector3
+{
+    fn deserialize<D : Deserializer<'de>>(deserializer : D) -> Result<Vector3, D::Error>
+    {
+        let v = deserialize_floats(deserializer, "Vector3", "an array of 3 numbers or a map with x, y and z", VECTOR3_FIELDS, false)?;
+        Ok(Vector3::new(v[0], v[1], v[2]))
+    }
+}
+
+impl<'de> Deserialize<'de> for Named<Vector3>
+{
+    fn deserialize<D : Deserializer<'de>>(deserializer : D) -> Result<Named<Vector3>, D::Error>
+    {
+        let v = deserialize_floats(deserializer, "Vector3", "a map with x, y and z", VECTOR3_FIELDS, true)?;
+        Ok(Named(Vector3::new(v[0], v[1], v[2])))
+    }
+}
+
+impl<'de> Deserialize<'de> for Vector4
+{
+    fn deserialize<D : Deserializer<'de>>(deserializer : D) -> Result<Vector4, D::Error>
+    {
+        let v = deserialize_floats(deserializer, "Vector4", "an array of 4 numbers or a map with x, y, z and w", VECTOR4_FIELDS, false)?;
+        Ok(Vector4::new(v[0], v[1], v[2], v[3]))
+    }
+}
+
+impl<'de> Deserialize<'de> for Named<Vector4>
+{
+    fn deserialize<D : Deserializer<'de>>(deserializer : D) -> Result<Named<Vector4>, D::Error>
+    {
+        let v = deserialize_floats(deserializer, "Vector4", "a map with x, y, z and w", VECTOR4_FIELDS, true)?;
+        Ok(Named(Vector4::new(v[0], v[1], v[2], v[3])))
+    }
+}
+
+// Reads the tagged `{ order, values }` form; human readable maps may use cell names instead
+struct MatrixVisitor;
+
+fn from_order<E : Error>(order : &str, values : Vec<f32>) -> Result<AffineMatrix, E>
+{
+    match order {
+        "row_major" => Ok(AffineMatrix::from_row_major(values)),
+        "column_major" => Ok(AffineMatrix::from_column_major(values)),
+        other => Err(E::unknown_variant(other, &["row_major", "column_major"])),
+    }
+}
+
+impl<'de> Visitor<'de> for MatrixVisitor
+{
+    type Value = AffineMatrix;
+
+    fn expecting(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        f.write_str("a matrix with an order and 16 values, or 16 named cells")
+    }
+
+    fn visit_seq<A : SeqAccess<'de>>(self, mut seq : A) -> Result<AffineMatrix, A::Error>
+    {
+        let order : String = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(0, &self))?;
+        let values : [f32; 16] = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(1, &self))?;
+        from_order(&order, values.to_vec())
+    }
+
+    fn visit_map<A : MapAccess<'de>>(self, mut map : A) -> Result<AffineMatrix, A::Error>
+    {
+        let mut order : Option<String> = None;
+        let mut values : Option<Vec<f32>> = None;
+        let mut cells : Vec<Option<f32>> = vec![None; 16];
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "order" if order.is_some() => return Err(A::Error::duplicate_field("order")),
+                "order" => order = Some(map.next_value()?),
+                "values" if values.is_some() => return Err(A::Error::duplicate_field("values")),
+                "values" => values = Some(map.next_value::<[f32; 16]>()?.to_vec()),
+                _ => match CELL_FIELDS.iter().position(|f| *f == key) {
+                    Some(index) if cells[index].is_some() => return Err(A::Error::duplicate_field(CELL_FIELDS[index])),
+                    Some(index) => cells[index] = Some(map.next_value()?),
+                    None => return Err(A::Error::unknown_field(&key, MATRIX_FIELDS)),
+                },
+            }
+        }
+
+        // one form or the other, so no cell is silently dropped
+        if values.is_some() && cells.iter().any(|c| c.is_some()) {
+            return Err(A::Error::custom("a matrix has either \"values\" or named cells, not both"));
+        }
+
+        match (order, values) {
+            (Some(order), Some(values)) => from_order(&order, values),
+            (None, Some(_)) => Err(A::Error::missing_field("order")),
+            (_, None) if cells.iter().any(|c| c.is_some()) => {
+                let values = cells.iter().zip(CELL_FIELDS)
+                    .map(|(c, f)| c.ok_or_else(|| A::Error::missing_field(f)))
+                    .collect::<Result<Vec<f32>, A::Error>>()?;
+                Ok(AffineMatrix::from_row_major(values))
+            },
+            (_, None) => Err(A::Error::missing_field("values")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for AffineMatrix
+{
+    fn deserialize<D : Deserializer<'de>>(deserializer : D) -> Result<AffineMatrix, D::Error>
+    {
+        deserializer.deserialize_struct("AffineMatrix", MATRIX_FIELDS, MatrixVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for ColumnMajor
+{
+    fn deserialize<D : Deserializer<'de>>(deserializer : D) -> Result<ColumnMajor, D::Error>
+    {
+        AffineMatrix::deserialize(deserializer).map(ColumnMajor)
+    }
+}
+
+impl<'de> Deserialize<'de> for Named<AffineMatrix>
+{
+    fn deserialize<D : Deserializer<'de>>(deserializer : D) -> Result<Named<AffineMatrix>, D::Error>
+    {
+        if deserializer.is_human_readable() {
+            return deserializer.deserialize_struct("AffineMatrix", CELL_FIELDS, MatrixVisitor).map(Named);
+        }
+        let values = deserializer.deserialize_struct("AffineMatrix", CELL_FIELDS, FloatsVisitor { expecting: "16 matrix cells", fields: CELL_FIELDS })?;
+        Ok(Named(AffineMatrix::from_row_major(values)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate serde_json;
+    extern crate postcard;
+
+    use ::vector3::Vector3;
+    use ::vector4::Vector4;
+    use ::matrices::AffineMatrix;
+    use ::serde_support::{ Named, ColumnMajor, CELL_FIELDS };
+
+    fn sample() -> AffineMatrix {
+        AffineMatrix::from_row_major((1..17).map(|i| i as f32).collect())
+    }
+
+    #[test]
+    fn compact_vectors() {
+        assert_eq!(serde_json::to_string(&Vector3::newi(1, 2, 3)).unwrap(), "[1.0,2.0,3.0]");
+        assert_eq!(serde_json::from_str::<Vector4>("[1, 2, 3, 4]").unwrap(), Vector4::newi(1, 2, 3, 4));
+        assert!(serde_json::from_str::<Vector3>("[1, 2]").is_err());
+        assert!(serde_json::from_str::<Vector3>("[1, 2, 3, 4]").is_err());
+    }
+
+    #[test]
+    fn named_vectors() {
+        let json = serde_json::to_string(&Named(Vector3::newi(1, 2, 3))).unwrap();
+        assert_eq!(json, r#"{"x":1.0,"y":2.0,"z":3.0}"#);
+        // either form reads back as a plain vector
+        assert_eq!(serde_json::from_str::<Vector3>(&json).unwrap(), Vector3::newi(1, 2, 3));
+        assert!(serde_json::from_str::<Vector3>(r#"{"x":1,"y":2}"#).is_err());
+    }
+
+    #[test]
+    fn tagged_matrices() {
+        let json = serde_json::to_string(&sample()).unwrap();
+        assert!(json.starts_with(r#"{"order":"row_major","values":[1.0,2.0,3.0,4.0,5.0"#));
+        assert_eq!(serde_json::from_str::<AffineMatrix>(&json).unwrap(), sample());
+
+        let json = serde_json::to_string(&ColumnMajor(sample())).unwrap();
+        assert!(json.starts_with(r#"{"order":"column_major","values":[1.0,5.0,9.0,13.0,2.0"#));
+        assert_eq!(serde_json::from_str::<AffineMatrix>(&json).unwrap(), sample());
+
+        assert!(serde_json::from_str::<AffineMatrix>(r#"{"values":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0]}"#).is_err());
+        assert!(serde_json::from_str::<AffineMatrix>(r#"{"order":"diagonal","values":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0]}"#).is_err());
+    }
+
+    #[test]
+    fn named_matrices() {
+        let json = serde_json::to_string(&Named(AffineMatrix::translation(1., 2., 3.))).unwrap();
+        assert!(json.starts_with(r#"{"i1":1.0,"j1":0.0,"k1":0.0,"w1":1.0,"i2""#));
+        assert_eq!(serde_json::from_str::<AffineMatrix>(&json).unwrap(), AffineMatrix::translation(1., 2., 3.));
+    }
+
+    #[test]
+    fn ambiguous_matrices_rejected() {
+        let message = |json : &str| serde_json::from_str::<AffineMatrix>(json).unwrap_err().to_string();
+        let values = r#""values":[1,0,0,0,0,1,0,0,0,0,1,0,0,0,0,1]"#;
+
+        assert!(message(&format!(r#"{{"order":"row_major","order":"column_major",{}}}"#, values)).starts_with("duplicate field `order`"));
+        assert!(message(&format!(r#"{{"order":"row_major",{},{}}}"#, values, values)).starts_with("duplicate field `values`"));
+        let cells = CELL_FIELDS.iter().map(|c| format!(r#""{}":0"#, c)).collect::<Vec<String>>().join(",");
+        assert!(message(&format!(r#"{{{},"w1":2}}"#, cells)).starts_with("duplicate field `w1`"));
+        assert!(message(&format!(r#"{{"order":"row_major",{},"w1":2}}"#, values)).starts_with("a matrix has either"));
+        assert!(serde_json::from_str::<Vector3>(r#"{"x":1,"y":2,"z":3,"x":4}"#).unwrap_err().to_string().starts_with("duplicate field `x`"));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let bytes = postcard::to_allocvec(&Vector3::newi(1, 2, 3)).unwrap();
+        assert_eq!(bytes.len(), 12);
+        assert_eq!(postcard::from_bytes::<Vector3>(&bytes).unwrap(), Vector3::newi(1, 2, 3));
+
+        let bytes = postcard::to_allocvec(&ColumnMajor(sample())).unwrap();
+        assert_eq!(postcard::from_bytes::<AffineMatrix>(&bytes).unwrap(), sample());
+
+        let bytes = postcard::to_allocvec(&Named(sample())).unwrap();
+        assert_eq!(postcard::from_bytes::<Named<AffineMatrix>>(&bytes).unwrap(), Named(sample()));
+
+        let bytes = postcard::to_allocvec(&Named(Vector4::newi(1, 2, 3, 4))).unwrap();
+        assert_eq!(postcard::from_bytes::<Named<Vector4>>(&bytes).unwrap(), Named(Vector4::newi(1, 2, 3, 4)));
+    }
+}
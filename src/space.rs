@@ -0,0 +1,258 @@
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{ Add, Sub, Mul };
+use ::vector3::Vector3;
+use ::matrices::AffineMatrix;
+
+// Points, directions and transforms tagged with the coordinate space they live in, so that
+// mixing frames is a type error. Spaces are any type, usually an empty enum:
+//
+//     enum World {}
+//     enum View {}
+//     let view : Transform<World, View> = Transform::new(camera.inverse());
+//     let p : Point3<View> = view * Point3::<World>::new(1., 2., 3.);
+//
+// Typed composition reads like function application, `Transform<B, C> * Transform<A, B>`
+// gives `Transform<A, C>`; `a.then(b)` is the same in the left to right order of
+// AffineMatrix multiplication. The wrappers are repr(transparent), so they cost nothing.
+
+// fn() -> S keeps the wrappers Send, Sync and Copy whatever the space types are
+type Tag<S> = PhantomData<fn() -> S>;
+
+#[repr(transparent)]
+pub struct Point3<S>
+{
+    vector : Vector3,
+    space : Tag<S>,
+}
+
+#[repr(transparent)]
+pub struct Direction3<S>
+{
+    vector : Vector3,
+    space : Tag<S>,
+}
+
+#[repr(transparent)]
+pub struct Transform<From, To>
+{
+    matrix : AffineMatrix,
+    spaces : PhantomData<fn(From) -> To>,
+}
+
+impl<S> Point3<S>
+{
+    pub fn new(x : f32, y : f32, z : f32) -> Point3<S>
+    {
+        Point3::from_vector(Vector3::new(x, y, z))
+    }
+
+    pub fn from_vector(vector : Vector3) -> Point3<S>
+    {
+        Point3 { vector, space: PhantomData }
+    }
+
+    pub fn vector(&self) -> Vector3
+    {
+        self.vector
+    }
+}
+
+impl<S> Direction3<S>
+{
+    pub fn new(x : f32, y : f32, z : f32) -> Direction3<S>
+    {
+        Direction3::from_vector(Vector3::new(x, y, z))
+    }
+
+    pub fn from_vector(vector : Vector3) -> Direction3<S>
+    {
+        Direction3 { vector, space: PhantomData }
+    }
+
+    pub fn vector(&self) -> Vector3
+    {
+        self.vector
+    }
+}
+
+impl<From, To> Transform<From, To>
+{
+    // tags a matrix that maps From coordinates to To coordinates; nothing checks that it does
+    pub fn new(matrix : AffineMatrix) -> Transform<From, To>
+    {
+        Transform { matrix, spaces: PhantomData }
+    }
+
+    pub fn matrix(&self) -> AffineMatrix
+    {
+        self.matrix
+    }
+
+    pub fn inverse(&self) -> Transform<To, From>
+    {
+        Transform::new(self.matrix.inverse())
+    }
+
+    // this transform followed by `next`
+    pub fn then<Next>(&self, next : Transform<To, Next>) -> Transform<From, Next>
+    {
+        Transform::new(self.matrix * next.matrix)
+    }
+
+    pub fn apply(&self, p : Point3<From>) -> Point3<To>
+    {
+        Point3::from_vector(self.matrix.apply_vec3(p.vector))
+    }
+
+    pub fn apply_direction(&self, d : Direction3<From>) -> Direction3<To>
+    {
+        Direction3::from_vector(self.matrix.apply_direction(d.vector))
+    }
+}
+
+impl<S> Transform<S, S>
+{
+    pub fn identity() -> Transform<S, S>
+    {
+        Transform::new(AffineMatrix::identity())
+    }
+}
+
+impl<A, B, C> Mul<Transform<A, B>> for Transform<B, C>
+{
+    type Output = Transform<A, C>;
+    fn mul(self, first : Transform<A, B>) -> Transform<A, C> {
+        first.then(self)
+    }
+}
+
+impl<From, To> Mul<Point3<From>> for Transform<From, To>
+{
+    type Output = Point3<To>;
+    fn mul(self, p : Point3<From>) -> Point3<To> {
+        self.apply(p)
+    }
+}
+
+impl<From, To> Mul<Direction3<From>> for Transform<From, To>
+{
+    type Output = Direction3<To>;
+    fn mul(self, d : Direction3<From>) -> Direction3<To> {
+        self.apply_direction(d)
+    }
+}
+
+impl<S> Sub for Point3<S>
+{
+    type Output = Direction3<S>;
+    fn sub(self, p : Point3<S>) -> Direction3<S> {
+        Direction3::from_vector(self.vector - p.vector)
+    }
+}
+
+impl<S> Add<Direction3<S>> for Point3<S>
+{
+    type Output = Point3<S>;
+    fn add(self, d : Direction3<S>) -> Point3<S> {
+        Point3::from_vector(self.vector + d.vector)
+    }
+}
+
+impl<S> Add for Direction3<S>
+{
+    type Output = Direction3<S>;
+    fn add(self, d : Direction3<S>) -> Direction3<S> {
+        Direction3::from_vector(self.vector + d.vector)
+    }
+}
+
+// derives would require the space types themselves to be Clone, Debug and so on
+
+impl<S> Clone for Point3<S> { fn clone(&self) -> Point3<S> { *self } }
+impl<S> Copy for Point3<S> {}
+impl<S> PartialEq for Point3<S> { fn eq(&self, p : &Point3<S>) -> bool { self.vector == p.vector } }
+
+impl<S> Clone for Direction3<S> { fn clone(&self) -> Direction3<S> { *self } }
+impl<S> Copy for Direction3<S> {}
+impl<S> PartialEq for Direction3<S> { fn eq(&self, d : &Direction3<S>) -> bool { self.vector == d.vector } }
+
+impl<From, To> Clone for Transform<From, To> { fn clone(&self) -> Transform<From, To> { *self } }
+impl<From, To> Copy for Transform<From, To> {}
+impl<From, To> PartialEq for Transform<From, To> { fn eq(&self, t : &Transform<From, To>) -> bool { self.matrix == t.matrix } }
+
+impl<S> fmt::Debug for Point3<S>
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_tuple("Point3").field(&self.vector).finish()
+    }
+}
+
+impl<S> fmt::Debug for Direction3<S>
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_tuple("Direction3").field(&self.vector).finish()
+    }
+}
+
+impl<From, To> fmt::Debug for Transform<From, To>
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        f.debug_tuple("Transform").field(&self.matrix).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::space::{ Point3, Direction3, Transform };
+    use ::matrices::AffineMatrix;
+    use ::vector3::Vector3;
+    use std::mem;
+
+    enum Model {}
+    enum World {}
+    enum View {}
+
+    fn model_to_world() -> Transform<Model, World> {
+        Transform::new(AffineMatrix::translation(10., 0., 0.))
+    }
+
+    fn world_to_view() -> Transform<World, View> {
+        Transform::new(AffineMatrix::uniform_scale(2.))
+    }
+
+    #[test]
+    fn composition_lines_up_spaces() {
+        let model_to_view : Transform<Model, View> = world_to_view() * model_to_world();
+        assert_eq!(model_to_view, model_to_world().then(world_to_view()));
+
+        let p : Point3<View> = model_to_view * Point3::<Model>::new(1., 0., 0.);
+        assert_eq!(p.vector(), Vector3::newi(22, 0, 0));
+    }
+
+    #[test]
+    fn inverse_swaps_spaces() {
+        let back : Transform<View, World> = world_to_view().inverse();
+        let p = back * (world_to_view() * Point3::<World>::new(1., 2., 3.));
+        assert_eq!(p, Point3::new(1., 2., 3.));
+        assert_eq!(Transform::<World, World>::identity(), world_to_view().then(back));
+    }
+
+    #[test]
+    fn directions_ignore_translation() {
+        let a = Point3::<Model>::new(1., 1., 1.);
+        let b = Point3::<Model>::new(2., 1., 1.);
+        let d : Direction3<World> = model_to_world() * (b - a);
+        assert_eq!(d.vector(), Vector3::newi(1, 0, 0));
+        assert_eq!(a + (b - a), b);
+    }
+
+    #[test]
+    fn zero_cost() {
+        assert_eq!(mem::size_of::<Point3<World>>(), mem::size_of::<Vector3>());
+        assert_eq!(mem::size_of::<Transform<Model, View>>(), mem::size_of::<AffineMatrix>());
+    }
+}
@@ -0,0 +1,442 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::io::{ Read, Write };
+use ::vector3::{ Vec3, Vector3 };
+use ::matrices::AffineMatrix;
+use ::mesh::{ Mesh, unit_or_zero };
+
+const HEADER_BYTES : usize = 80;
+const FACET_BYTES : usize = 50;
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub enum StlFormat
+{
+    Ascii,
+    Binary,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+#[derive(Copy)]
+pub struct Facet
+{
+    pub normal : Vector3,
+    pub vertices : [Vector3; 3],
+    pub attribute : u16,  // binary 'attribute byte count', usually 0
+}
+
+// The name is the ASCII solid name, or the binary header up to its first NUL
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Clone)]
+pub struct Stl
+{
+    pub name : String,
+    pub facets : Vec<Facet>,
+}
+
+#[derive(Debug)]
+pub enum StlError
+{
+    Io(io::Error),
+    Truncated { expected : usize, found : usize },
+    Parse { line : usize, message : String },
+}
+
+impl fmt::Display for StlError
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        match self {
+            StlError::Io(e) => write!(f, "STL io error: {}", e),
+            StlError::Truncated { expected, found } => write!(f, "binary STL truncated: expected {} bytes, found {}", expected, found),
+            StlError::Parse { line, message } => write!(f, "STL parse error on line {}: {}", line, message),
+        }
+    }
+}
+
+impl Error for StlError {}
+
+impl From<io::Error> for StlError
+{
+    fn from(e : io::Error) -> StlError { StlError::Io(e) }
+}
+
+impl Facet
+{
+    // facet with its normal computed from counter-clockwise winding
+    pub fn new(a : Vector3, b : Vector3, c : Vector3) -> Facet
+    {
+        Facet { normal: unit_or_zero((b - a).cross(c - a)), vertices: [a, b, c], attribute: 0 }
+    }
+}
+
+impl Stl
+{
+    pub fn new(name : &str, facets : Vec<Facet>) -> Stl
+    {
+        Stl { name: name.to_string(), facets }
+    }
+
+    // Binary files are recognized by their exact size, since some exporters also start
+    // the binary header with "solid". Anything else starting with "solid" is ASCII, unless
+    // the header and count hold control bytes no text file would, as a truncated binary
+    // file's zero padding and count do; that is read as binary to report the truncation.
+    pub fn detect_format(bytes : &[u8]) -> StlFormat
+    {
+        if bytes.len() >= HEADER_BYTES + 4 && binary_size(binary_count(bytes)) == Some(bytes.len()) {
+            return StlFormat::Binary;
+        }
+        let start = bytes.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(bytes.len());
+        let prefix = &bytes[..bytes.len().min(HEADER_BYTES + 4)];
+        let text = prefix.iter().all(|&b| b >= 0x20 || b.is_ascii_whitespace());
+        if bytes[start..].starts_with(b"solid") && text { StlFormat::Ascii } else { StlFormat::Binary }
+    }
+
+    pub fn read<R : Read>(mut reader : R) -> Result<Stl, StlError>
+    {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        Stl::from_bytes(&bytes)
+    }
+
+    pub fn read_transformed<R : Read>(reader : R, m : &AffineMatrix) -> Result<Stl, StlError>
+    {
+        Stl::read(reader).map(|stl| stl.apply(m))
+    }
+
+    pub fn from_bytes(bytes : &[u8]) -> Result<Stl, StlError>
+    {
+        match Stl::detect_format(bytes) {
+            StlFormat::Ascii => Stl::parse_ascii(&String::from_utf8_lossy(bytes)),
+            StlFormat::Binary => Stl::parse_binary(bytes),
+        }
+    }
+
+    pub fn parse_binary(bytes : &[u8]) -> Result<Stl, StlError>
+    {
+        if bytes.len() < HEADER_BYTES + 4 {
+            return Err(StlError::Truncated { expected: HEADER_BYTES + 4, found: bytes.len() });
+        }
+        let count = binary_count(bytes);
+        // a count too large to address cannot be in memory either
+        let expected = binary_size(count).unwrap_or(usize::MAX);
+        if bytes.len() < expected {
+            return Err(StlError::Truncated { expected, found: bytes.len() });
+        }
+
+        let header = &bytes[..HEADER_BYTES];
+        let name_end = header.iter().position(|&b| b == 0).unwrap_or(HEADER_BYTES);
+        let name = String::from_utf8_lossy(&header[..name_end]).trim().to_string();
+
+        let facets = bytes[HEADER_BYTES + 4..expected].chunks(FACET_BYTES).map(|f| {
+            let v = |i : usize| Vector3::new(read_f32(f, i * 12), read_f32(f, i * 12 + 4), read_f32(f, i * 12 + 8));
+            Facet { normal: v(0), vertices: [v(1), v(2), v(3)], attribute: u16::from_le_bytes([f[48], f[49]]) }
+        }).collect();
+
+        Ok(Stl { name, facets })
+    }
+
+    pub fn parse_ascii(text : &str) -> Result<Stl, StlError>
+    {
+        let mut name = None;
+        let mut facets = vec![];
+        let mut normal = None;
+        let mut vertices = vec![];
+
+        for (number, line) in text.lines().enumerate() {
+            let number = number + 1;
+            let error = |message : String| StlError::Parse { line: number, message };
+            let tokens : Vec<&str> = line.split_whitespace().collect();
+
+            match tokens.first().cloned() {
+                None | Some("outer") | Some("endloop") => {},
+                Some("solid") => name = Some(tokens[1..].join(" ")),
+                Some("endsolid") => {
+                    return match name {
+                        Some(name) => Ok(Stl { name, facets }),
+                        None => Err(error("'endsolid' without 'solid'".to_string())),
+                    };
+                },
+                Some("facet") => {
+                    if tokens.get(1) != Some(&"normal") {
+                        return Err(error("expected 'facet normal'".to_string()));
+                    }
+                    normal = Some(parse_vector(&tokens[2..]).map_err(error)?);
+                    vertices.clear();
+                },
+                Some("vertex") => {
+                    if normal.is_none() {
+                        return Err(error("vertex outside of a facet".to_string()));
+                    }
+                    vertices.push(parse_vector(&tokens[1..]).map_err(error)?);
+                },
+                Some("endfacet") => {
+                    if vertices.len() != 3 {
+                        return Err(error(format!("facet has {} vertices, expected 3", vertices.len())));
+                    }
+                    let n = match normal.take() {
+                        Some(n) => n,
+                        None => return Err(error("'endfacet' without 'facet'".to_string())),
+                    };
+                    facets.push(Facet { normal: n, vertices: [vertices[0], vertices[1], vertices[2]], attribute: 0 });
+                },
+                Some(other) => return Err(error(format!("unexpected '{}'", other))),
+            }
+        }
+
+        Err(StlError::Parse { line: text.lines().count(), message: "missing 'endsolid'".to_string() })
+    }
+
+    pub fn write<W : Write>(&self, w : &mut W, format : StlFormat) -> io::Result<()>
+    {
+        match format {
+            StlFormat::Ascii => self.write_ascii(w),
+            StlFormat::Binary => self.write_binary(w),
+        }
+    }
+
+    pub fn write_ascii<W : Write>(&self, w : &mut W) -> io::Result<()>
+    {
+        writeln!(w, "solid {}", self.name)?;
+        for f in &self.facets {
+            writeln!(w, "  facet normal {} {} {}", f.normal.x(), f.normal.y(), f.normal.z())?;
+            writeln!(w, "    outer loop")?;
+            for v in &f.vertices {
+                writeln!(w, "      vertex {} {} {}", v.x(), v.y(), v.z())?;
+            }
+            writeln!(w, "    endloop")?;
+            writeln!(w, "  endfacet")?;
+        }
+        writeln!(w, "endsolid {}", self.name)
+    }
+
+    pub fn write_binary<W : Write>(&self, w : &mut W) -> io::Result<()>
+    {
+        let mut header = [0u8; HEADER_BYTES];
+        let name = self.name.as_bytes();
+        let length = name.len().min(HEADER_BYTES);
+        header[..length].copy_from_slice(&name[..length]);
+        w.write_all(&header)?;
+        w.write_all(&(self.facets.len() as u32).to_le_bytes())?;
+
+        for f in &self.facets {
+            for v in [f.normal, f.vertices[0], f.vertices[1], f.vertices[2]].iter() {
+                w.write_all(&v.x().to_le_bytes())?;
+                w.write_all(&v.y().to_le_bytes())?;
+                w.write_all(&v.z().to_le_bytes())?;
+            }
+            w.write_all(&f.attribute.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    // Places every vertex through m and recomputes facet normals from the new
+    // geometry; mirroring transforms swap two vertices to keep the winding outward.
+    pub fn apply(&self, m : &AffineMatrix) -> Stl
+    {
+        let flip = m.determinant() < 0.;
+        let facets = self.facets.iter().map(|f| {
+            let a = m.apply_vec3(f.vertices[0]);
+            let b = m.apply_vec3(f.vertices[1]);
+            let c = m.apply_vec3(f.vertices[2]);
+            let facet = if flip { Facet::new(a, c, b) } else { Facet::new(a, b, c) };
+            Facet { attribute: f.attribute, ..facet }
+        }).collect();
+        Stl { name: self.name.clone(), facets }
+    }
+
+    // (min, max) corners of the axis aligned bounding box, None without facets
+    pub fn bounding_box(&self) -> Option<(Vector3, Vector3)>
+    {
+        self.to_mesh().bounding_box()
+    }
+
+    // unwelded mesh with three vertices per facet, each carrying the facet normal
+    pub fn to_mesh(&self) -> Mesh
+    {
+        let positions = self.facets.iter().flat_map(|f| f.vertices.to_vec()).collect();
+        let normals = self.facets.iter().flat_map(|f| vec![f.normal; 3]).collect();
+        let indices = (0..self.facets.len() as u32 * 3).collect();
+        Mesh::new(positions, indices).with_normals(normals)
+    }
+
+    pub fn from_mesh(name : &str, mesh : &Mesh) -> Stl
+    {
+        Stl::new(name, mesh.triangles().iter().map(|t| Facet::new(t[0], t[1], t[2])).collect())
+    }
+}
+
+fn binary_count(bytes : &[u8]) -> usize
+{
+    u32::from_le_bytes([bytes[80], bytes[81], bytes[82], bytes[83]]) as usize
+}
+
+// the size of a binary file with `count` facets, or None if that overflows usize, as a
+// count read from the file can on 32-bit targets
+fn binary_size(count : usize) -> Option<usize>
+{
+    FACET_BYTES.checked_mul(count).and_then(|facets| facets.checked_add(HEADER_BYTES + 4))
+}
+
+fn read_f32(bytes : &[u8], offset : usize) -> f32
+{
+    f32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+fn parse_vector(args : &[&str]) -> Result<Vector3, String>
+{
+    if args.len() != 3 {
+        return Err(format!("expected 3 coordinates, found {}", args.len()));
+    }
+    let mut values = [0.; 3];
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = arg.parse::<f32>().map_err(|_| format!("invalid number '{}'", arg))?;
+    }
+    Ok(Vector3::new(values[0], values[1], values[2]))
+}
+
+#[cfg(test)]
+mod tests {
+    use ::vector3::{ Vec3, Vector3 };
+    use ::matrices::AffineMatrix;
+    use ::stl::{ Stl, StlError, StlFormat, Facet, FACET_BYTES, binary_size };
+
+    const TRIANGLE : &str = "\
+solid part
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid part
+";
+
+    fn part() -> Stl {
+        Stl::new("part", vec![
+            Facet::new(Vector3::zero(), Vector3::i_hat(), Vector3::j_hat()),
+            Facet::new(Vector3::zero(), Vector3::k_hat(), Vector3::i_hat()),
+        ])
+    }
+
+    #[test]
+    fn parse_ascii() {
+        let stl = Stl::from_bytes(TRIANGLE.as_bytes()).unwrap();
+        assert_eq!(stl.name, "part");
+        assert_eq!(stl.facets.len(), 1);
+        assert_eq!(stl.facets[0].normal, Vector3::k_hat());
+        assert_eq!(stl.facets[0].vertices[2], Vector3::j_hat());
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        let mut out = vec![];
+        part().write_ascii(&mut out).unwrap();
+        assert_eq!(Stl::detect_format(&out), StlFormat::Ascii);
+        assert_eq!(Stl::read(&out[..]).unwrap(), part());
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let mut out = vec![];
+        part().write_binary(&mut out).unwrap();
+        assert_eq!(out.len(), 84 + 2 * 50);
+        assert_eq!(Stl::detect_format(&out), StlFormat::Binary);
+        assert_eq!(Stl::read(&out[..]).unwrap(), part());
+    }
+
+    #[test]
+    fn binary_header_starting_with_solid() {
+        // exporters commonly write "solid" into the binary header
+        let mut out = vec![];
+        Stl::new("solid body", part().facets).write(&mut out, StlFormat::Binary).unwrap();
+        assert_eq!(Stl::detect_format(&out), StlFormat::Binary);
+        assert_eq!(Stl::read(&out[..]).unwrap().facets, part().facets);
+    }
+
+    #[test]
+    fn truncated_binary() {
+        let mut out = vec![];
+        part().write_binary(&mut out).unwrap();
+        out.truncate(100);
+        match Stl::parse_binary(&out) {
+            Err(StlError::Truncated { expected, found }) => {
+                assert_eq!(expected, 184);
+                assert_eq!(found, 100);
+            },
+            other => panic!("expected truncation error, got {:?}", other),
+        }
+        assert!(Stl::from_bytes(&[0u8; 40]).is_err());
+    }
+
+    #[test]
+    fn binary_size_overflow() {
+        assert_eq!(binary_size(2), Some(184));
+        assert_eq!(binary_size(usize::MAX / FACET_BYTES + 1), None);
+
+        // the largest count a file can hold is only a truncation, never an overflow panic
+        let mut out = vec![];
+        part().write_binary(&mut out).unwrap();
+        out[80..84].copy_from_slice(&u32::MAX.to_le_bytes());
+        match Stl::from_bytes(&out) {
+            Err(StlError::Truncated { expected, found }) => {
+                assert_eq!(expected, binary_size(u32::MAX as usize).unwrap_or(usize::MAX));
+                assert_eq!(found, 184);
+            },
+            other => panic!("expected truncation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn truncated_binary_with_solid_header() {
+        let mut out = vec![];
+        Stl::new("solid body", part().facets).write(&mut out, StlFormat::Binary).unwrap();
+        out.truncate(100);
+        assert_eq!(Stl::detect_format(&out), StlFormat::Binary);
+        match Stl::read(&out[..]) {
+            Err(StlError::Truncated { expected, found }) => {
+                assert_eq!(expected, 184);
+                assert_eq!(found, 100);
+            },
+            other => panic!("expected truncation error, got {:?}", other),
+        }
+        match Stl::read(&out[..60]) {
+            Err(StlError::Truncated { expected, .. }) => assert_eq!(expected, 84),
+            other => panic!("expected truncation error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ascii_errors_report_line() {
+        let broken = TRIANGLE.replace("vertex 1 0 0", "vertex 1 0");
+        match Stl::parse_ascii(&broken) {
+            Err(StlError::Parse { line, .. }) => assert_eq!(line, 5),
+            other => panic!("expected parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn placement_recomputes_normals() {
+        let placed = part().apply(&(AffineMatrix::rotation_x(::std::f32::consts::PI / 2.) * AffineMatrix::translation(0., 0., 2.)));
+        let n = placed.facets[0].normal;
+        assert_approx_eq!(n, -Vector3::j_hat(), epsilon = 0.000001);
+        assert_approx_eq!(placed.facets[0].vertices[0], Vector3::newi(0, 0, 2), epsilon = 0.000001);
+
+        let (min, _) = placed.bounding_box().unwrap();
+        assert_approx_eq!(min.z(), 2., epsilon = 0.000001);
+    }
+
+    #[test]
+    fn mirrored_placement_keeps_normals_outward() {
+        let mirrored = part().apply(&AffineMatrix::scale(1., 1., -1.));
+        assert_eq!(mirrored.facets[0].normal, -Vector3::k_hat());
+        assert_eq!(mirrored.facets[0].vertices[1], Vector3::j_hat());
+    }
+}
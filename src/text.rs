@@ -0,0 +1,58 @@
+use std::error::Error;
+use std::fmt;
+
+// Shared helpers for the Display and FromStr impls of the vector and matrix types
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct ParseError
+{
+    pub message : String,
+}
+
+impl fmt::Display for ParseError
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+// Numbers separated by whitespace, commas or semicolons. The brackets written by the
+// Display impls are ignored, so "<1, 2, 3>", "[1 2 3]" and "1,2,3" all read the same.
+pub fn parse_numbers(s : &str, count : usize, what : &str) -> Result<Vec<f32>, ParseError>
+{
+    let values = s
+        .split(|c : char| c.is_whitespace() || ",;<>()[]".contains(c))
+        .filter(|token| !token.is_empty())
+        .map(|token| token.parse::<f32>().map_err(|_| ParseError { message: format!("invalid number '{}' in {}", token, what) }))
+        .collect::<Result<Vec<f32>, ParseError>>()?;
+
+    if values.len() != count {
+        return Err(ParseError { message: format!("{} needs {} numbers, found {}", what, count, values.len()) });
+    }
+    Ok(values)
+}
+
+// honors the formatter's precision, e.g. `{:.3}`
+pub fn format_number(v : f32, f : &fmt::Formatter) -> String
+{
+    match f.precision() {
+        Some(p) => format!("{:.*}", p, v),
+        None => v.to_string(),
+    }
+}
+
+// Writes cells as a matrix with `columns` per row: "[1 0; 0 1]" on one line, or with `{:#}`
+// one row per line. Cells are padded to a common width so the columns line up.
+pub fn format_rows(cells : &[f32], columns : usize, f : &mut fmt::Formatter) -> fmt::Result
+{
+    let cells : Vec<String> = cells.iter().map(|&v| format_number(v, f)).collect();
+    let width = cells.iter().map(|c| c.len()).max().unwrap_or(0);
+    let rows : Vec<String> = cells.chunks(columns)
+        .map(|row| row.iter().map(|c| format!("{:>width$}", c, width = width)).collect::<Vec<String>>().join(" "))
+        .collect();
+    write!(f, "[{}]", rows.join(if f.alternate() { "\n " } else { "; " }))
+}
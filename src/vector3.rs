@@ -1,9 +1,13 @@
+use std::fmt;
 use std::ops::{ Add, Sub, Mul, Neg };
+use std::str::FromStr;
+use ::text::{ ParseError, parse_numbers, format_number };
 
 #[derive(Debug)]
 #[derive(PartialEq)]
 #[derive(Clone)]
 #[derive(Copy)]
+#[repr(C)]
 pub struct Vector3
 {
     x : f32,
@@ -22,6 +26,18 @@ impl Vector3
     {
         Vector3 { x: x as f32, y: y as f32, z: z as f32 }
     }
+
+    // Two unit vectors completing a unit vector to a right-handed basis (b1, b2, self).
+    // Frisvad's construction with the sign fix from Duff et al., "Building an Orthonormal
+    // Basis, Revisited" (2017), which stays accurate as self approaches -Z.
+    pub fn orthonormal_basis(&self) -> (Vector3, Vector3)
+    {
+        let sign = 1f32.copysign(self.z);
+        let a = -1. / (sign + self.z);
+        let b = self.x * self.y * a;
+        (Vector3 { x: 1. + sign * self.x * self.x * a, y: sign * b, z: -sign * self.x },
+         Vector3 { x: b, y: sign + self.y * self.y * a, z: -self.y })
+    }
 }
 
 pub trait Vec3<T>
@@ -167,6 +183,24 @@ impl Neg for Vector3
     }
 }
 
+impl fmt::Display for Vector3
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "<{}, {}, {}>", format_number(self.x, f), format_number(self.y, f), format_number(self.z, f))
+    }
+}
+
+impl FromStr for Vector3
+{
+    type Err = ParseError;
+    fn from_str(s : &str) -> Result<Vector3, ParseError>
+    {
+        let v = parse_numbers(s, 3, "Vector3")?;
+        Ok(Vector3::new(v[0], v[1], v[2]))
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -265,4 +299,33 @@ mod tests {
     fn unary_sub() {
         assert_eq!(-Vector3::i_hat(), Vector3::newi(-1, 0, 0));
     }
+
+    #[test]
+    fn orthonormal_basis() {
+        for &n in &[Vector3::k_hat(), -Vector3::k_hat(), Vector3::newi(1, 2, -3).unit(), Vector3::new(0.001, 0., -1.).unit()] {
+            let (b1, b2) = n.orthonormal_basis();
+            assert_approx_eq!(b1.magnitude(), 1., epsilon = 0.00001);
+            assert_approx_eq!(b2.magnitude(), 1., epsilon = 0.00001);
+            assert_approx_eq!(b1.dot(n), 0., epsilon = 0.00001);
+            assert_approx_eq!(b2.dot(n), 0., epsilon = 0.00001);
+            assert_approx_eq!(b1.dot(b2), 0., epsilon = 0.00001);
+            assert_approx_eq!(b1.cross(b2), n, epsilon = 0.00001);
+        }
+    }
+
+    #[test]
+    fn display_round_trip() {
+        let v = Vector3::new(0.25, -4., 1e-7);
+        assert_eq!(v.to_string(), "<0.25, -4, 0.0000001>");
+        assert_eq!(v.to_string().parse::<Vector3>().unwrap(), v);
+        assert_eq!(format!("{:.2}", Vector3::new(1., 2.5, 1. / 3.)), "<1.00, 2.50, 0.33>");
+    }
+
+    #[test]
+    fn parse_loose_forms() {
+        assert_eq!("1 2 3".parse::<Vector3>().unwrap(), Vector3::newi(1, 2, 3));
+        assert_eq!("(1,2,3)".parse::<Vector3>().unwrap(), Vector3::newi(1, 2, 3));
+        assert!("<1, two, 3>".parse::<Vector3>().is_err());
+        assert!("<1, 2>".parse::<Vector3>().is_err());
+    }
 }
\ No newline at end of file
@@ -1,9 +1,13 @@
+use std::fmt;
 use std::ops::{ Add, Sub, Mul, Neg };
+use std::str::FromStr;
+use ::text::{ ParseError, parse_numbers, format_number };
 
 #[derive(Debug)]
 #[derive(PartialEq)]
 #[derive(Clone)]
 #[derive(Copy)]
+#[repr(C)]
 pub struct Vector4
 {
     x : f32,
@@ -135,6 +139,24 @@ impl Neg for Vector4
     }
 }
 
+impl fmt::Display for Vector4
+{
+    fn fmt(&self, f : &mut fmt::Formatter) -> fmt::Result
+    {
+        write!(f, "<{}, {}, {}, {}>", format_number(self.x, f), format_number(self.y, f), format_number(self.z, f), format_number(self.w, f))
+    }
+}
+
+impl FromStr for Vector4
+{
+    type Err = ParseError;
+    fn from_str(s : &str) -> Result<Vector4, ParseError>
+    {
+        let v = parse_numbers(s, 4, "Vector4")?;
+        Ok(Vector4::new(v[0], v[1], v[2], v[3]))
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
@@ -233,4 +255,12 @@ mod tests {
     fn unary_sub() {
         assert_eq!(-Vector4::i_hat(), Vector4::newi(-1, 0, 0, 0));
     }
+
+    #[test]
+    fn display_round_trip() {
+        let v = Vector4::new(1.5, -2., 0., 1.);
+        assert_eq!(v.to_string(), "<1.5, -2, 0, 1>");
+        assert_eq!(v.to_string().parse::<Vector4>().unwrap(), v);
+        assert!("<1, 2, 3>".parse::<Vector4>().is_err());
+    }
 }
\ No newline at end of file
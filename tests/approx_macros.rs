@@ -0,0 +1,23 @@
+// Uses the exported assertion macros the way a downstream crate would: imported by path,
+// without #[macro_use], so every macro they expand to must be reachable through $crate.
+extern crate affine_transforms;
+
+use affine_transforms::{ assert_approx_eq, assert_relative_eq, assert_ulps_eq };
+use affine_transforms::matrices::AffineMatrix;
+use affine_transforms::vector3::Vector3;
+
+#[test]
+fn macros_resolve_by_path() {
+    assert_approx_eq!(0.1f32 + 0.2, 0.3);
+    assert_approx_eq!(Vector3::newi(1, 2, 3), Vector3::new(1.0001, 2., 3.), epsilon = 0.001);
+    assert_relative_eq!(1000000f32, 1000001f32);
+    assert_relative_eq!(AffineMatrix::rotation_z(0.5) * AffineMatrix::rotation_z(-0.5), AffineMatrix::identity(), max_relative = 0.0001);
+    assert_ulps_eq!(1f32, 1f32 + f32::EPSILON);
+    assert_ulps_eq!(1f32, 1f32 + f32::EPSILON, max_ulps = 1);
+}
+
+#[test]
+#[should_panic(expected = "left ≈ right")]
+fn failures_still_panic() {
+    assert_approx_eq!(Vector3::newi(1, 2, 3), Vector3::newi(1, 2, 4));
+}